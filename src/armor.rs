@@ -0,0 +1,122 @@
+use crate::errors::EncItError;
+
+pub const ARMOR_BEGIN: &str = "-----BEGIN ENCIT MESSAGE-----";
+pub const ARMOR_END: &str = "-----END ENCIT MESSAGE-----";
+const ARMOR_LINE_LEN: usize = 64;
+const ARMOR_HEADER_SEPARATOR: &str = ": ";
+pub const ARMOR_VERSION_HEADER: &str = "Version";
+pub const ARMOR_COMMENT_HEADER: &str = "Comment";
+
+/// Wraps an encrypted message between the armor markers. The informational headers are
+/// not protected, they are followed by an empty line and by the message in lines of 64
+/// characters. The JWE serializations contain no whitespace, so the lines are simply joined
+/// back by `dearmor`.
+pub fn armor(message: &str, headers: &[(&str, &str)]) -> Result<String, EncItError> {
+    let mut armored = format!("{}\n", ARMOR_BEGIN);
+    for (name, value) in headers {
+        if name.contains(ARMOR_HEADER_SEPARATOR) || name.contains('\n') || value.contains('\n') {
+            return Err(EncItError::EncodeError(format!(
+                "invalid armor header {}",
+                name
+            )));
+        }
+        armored.push_str(&format!("{}{}{}\n", name, ARMOR_HEADER_SEPARATOR, value));
+    }
+    if !headers.is_empty() {
+        armored.push('\n');
+    }
+    for line in message.trim().as_bytes().chunks(ARMOR_LINE_LEN) {
+        armored.push_str(&String::from_utf8(line.to_vec())?);
+        armored.push('\n');
+    }
+    armored.push_str(ARMOR_END);
+    armored.push('\n');
+    Ok(armored)
+}
+
+/// The encrypted message contained in the input: the text between the armor markers
+/// without the headers, otherwise the trimmed input (compact or JSON serialization,
+/// clear-signed message).
+pub fn dearmor(input: &str) -> Result<String, EncItError> {
+    let invalid_format = || EncItError::DecodeError("invalid armored message".to_string());
+    let (before, armored) = match input.split_once(ARMOR_BEGIN) {
+        Some(split) => split,
+        None => return Ok(input.trim().to_string()),
+    };
+    // the armor can be quoted, e.g. "> " in an email reply
+    let quote = before.rsplit('\n').next().unwrap_or_default().trim();
+    // the text after the end marker is ignored
+    let (armored, _) = armored.split_once(ARMOR_END).ok_or_else(invalid_format)?;
+    let mut lines = armored
+        .lines()
+        .map(|line| line.trim())
+        .map(|line| line.strip_prefix(quote).unwrap_or(line).trim())
+        .skip_while(|line| line.is_empty())
+        .peekable();
+    // the optional headers end with an empty line, the message lines never contain ": "
+    if lines
+        .peek()
+        .is_some_and(|line| line.contains(ARMOR_HEADER_SEPARATOR))
+    {
+        for line in lines.by_ref() {
+            if line.is_empty() {
+                break;
+            }
+        }
+    }
+    let message: String = lines.collect();
+    if message.is_empty() {
+        return Err(invalid_format());
+    }
+    Ok(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COMPACT: &str = "eyJhbGciOiJFQ0RILUVTIn0.ZW5jcnlwdGVkIGtleQ.aXY.Y2lwaGVydGV4dCBjaXBoZXJ0ZXh0IGNpcGhlcnRleHQgY2lwaGVydGV4dCBjaXBoZXJ0ZXh0.dGFn";
+
+    #[test]
+    fn armor_dearmor() -> Result<(), EncItError> {
+        let armored = armor(COMPACT, &[(ARMOR_VERSION_HEADER, "encit 0.1.0")])?;
+        assert!(armored.starts_with(&format!("{}\nVersion: encit 0.1.0\n\n", ARMOR_BEGIN)));
+        assert!(armored.ends_with(&format!("{}\n", ARMOR_END)));
+        assert!(armored.lines().all(|line| line.len() <= ARMOR_LINE_LEN));
+        assert_eq!(dearmor(&armored)?, COMPACT);
+
+        // surrounding text, indentation and no headers
+        let armored = armor(COMPACT, &[])?.replace('\n', "\n  ");
+        let email = format!("Hi Bob,\n\n  {}\nBye,\nAlice\n", armored);
+        assert_eq!(dearmor(&email)?, COMPACT);
+
+        // quoted reply
+        let armored = armor(COMPACT, &[(ARMOR_COMMENT_HEADER, "for bob")])?;
+        let reply = armored
+            .lines()
+            .map(|line| format!("> {}\n", line))
+            .collect::<String>();
+        assert_eq!(
+            dearmor(&format!("On Monday Alice wrote:\n{}", reply))?,
+            COMPACT
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn dearmor_bare() -> Result<(), EncItError> {
+        assert_eq!(dearmor(&format!("\n{}\n", COMPACT))?, COMPACT);
+        let json = r#"{"protected":"e30","recipients":[{"header":{"rcp":"a"}}]}"#;
+        assert_eq!(dearmor(&format!("{}\n", json))?, json);
+        let armored = armor(json, &[(ARMOR_COMMENT_HEADER, "for bob")])?;
+        assert_eq!(dearmor(&armored)?, json);
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_armor() {
+        assert!(dearmor(&format!("{}\n{}\n", ARMOR_BEGIN, COMPACT)).is_err());
+        assert!(dearmor(&format!("{}\n{}\n", ARMOR_BEGIN, ARMOR_END)).is_err());
+        assert!(armor(COMPACT, &[(ARMOR_COMMENT_HEADER, "two\nlines")]).is_err());
+    }
+}
@@ -6,26 +6,30 @@ use std::io::Read;
 
 pub fn add_cmd<'a>(name: &str) -> App<'a, 'a> {
     SubCommand::with_name(name)
-        .arg(
-            Arg::with_name("name")
-                .long("name")
-                .short("n")
-                .takes_value(true)
-                .required(true),
-        )
-        .arg(
-            Arg::with_name("format")
-                .long("format")
-                .short("f")
-                .takes_value(true)
-                .required(true)
-                .possible_values(&["pem", "hex-pem", "base64-pem"]),
-        )
-        .arg(
-            Arg::with_name("key-file")
-                .takes_value(true)
-                .help("key file"),
-        )
+        .arg(name_arg().required(true))
+        .arg(format_arg().required(true))
+        .arg(key_file_arg())
+}
+
+pub fn name_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("name")
+        .long("name")
+        .short("n")
+        .takes_value(true)
+}
+
+pub fn format_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("format")
+        .long("format")
+        .short("f")
+        .takes_value(true)
+        .possible_values(&["pem", "hex-pem", "base64-pem"])
+}
+
+pub fn key_file_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("key-file")
+        .takes_value(true)
+        .help("key file")
 }
 
 pub fn get_key(
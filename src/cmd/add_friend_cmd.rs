@@ -1,27 +1,117 @@
-use crate::cmd::add_cmd::get_key;
+use crate::armor::dearmor;
+use crate::cmd::add_cmd::{format_arg, get_key, key_file_arg, name_arg};
 use crate::cmd::reader::{get_file_reader, EncItFileReader};
+use crate::config::EncItTrustLevel;
+use crate::enc::{EncIt, EncItImpl};
 use crate::EncItError::InvalidCommand;
 use crate::{EncItConfig, EncItError, EncItPEM};
-use clap::ArgMatches;
+use clap::{App, Arg, ArgMatches, SubCommand};
 use std::cell::RefCell;
+use std::fs::File;
+use std::io::{stdout, Read, Write};
 use std::rc::Rc;
 
+pub fn add_friend_cmd<'a>() -> App<'a, 'a> {
+    // the key comes from the key file, a message or a transition statement
+    SubCommand::with_name("friend")
+        .arg(name_arg().required_unless("transition"))
+        .arg(format_arg().required_unless_one(&["from-message", "transition"]))
+        .arg(key_file_arg())
+        .arg(
+            Arg::with_name("from-message")
+                .long("from-message")
+                .takes_value(true)
+                .conflicts_with_all(&["format", "key-file"])
+                .help("Import the sender key embedded in a message (trust on first use)"),
+        )
+        .arg(
+            Arg::with_name("identity")
+                .long("identity")
+                .short("i")
+                .takes_value(true)
+                .requires("from-message")
+                .help("Identity that received the message"),
+        )
+        .arg(
+            Arg::with_name("transition")
+                .long("transition")
+                .takes_value(true)
+                .conflicts_with_all(&["name", "format", "key-file", "from-message"])
+                .help("Replace the key of a friend with a transition statement signed by the old and new keys"),
+        )
+}
+
 pub fn add_friend_exec(
     arg_matches: &ArgMatches,
     config: Rc<dyn EncItConfig>,
 ) -> Result<(), EncItError> {
+    if let Some(statement_file) = arg_matches.value_of("transition") {
+        let enc_it = Rc::new(EncItImpl::new(config.clone()));
+        let statement_reader: Box<dyn EncItFileReader> = Box::new(File::open(statement_file)?);
+        return add_friend_transition(
+            config,
+            enc_it,
+            RefCell::new(statement_reader),
+            Rc::new(RefCell::new(stdout())),
+        );
+    }
+    if let Some(message_file) = arg_matches.value_of("from-message") {
+        let enc_it = Rc::new(EncItImpl::new(config.clone()));
+        let message_reader: Box<dyn EncItFileReader> = Box::new(File::open(message_file)?);
+        return add_friend_from_message(arg_matches, config, enc_it, RefCell::new(message_reader));
+    }
     let key_reader = RefCell::new(get_file_reader(arg_matches, "key-file")?);
     add_friend(arg_matches, config, key_reader)
 }
 
+fn add_friend_from_message(
+    arg_matches: &ArgMatches,
+    config: Rc<dyn EncItConfig>,
+    enc_it: Rc<dyn EncIt>,
+    message_reader: RefCell<Box<dyn EncItFileReader>>,
+) -> Result<(), EncItError> {
+    let mut message = String::new();
+    message_reader.borrow_mut().read_to_string(&mut message)?;
+    let key = enc_it.sender_key(&dearmor(&message)?, arg_matches.value_of("identity"))?;
+    save_friend(arg_matches, config, &key, EncItTrustLevel::Tofu)
+}
+
+/// the friend is found by the old key of the statement, its trust level is kept
+fn add_friend_transition(
+    config: Rc<dyn EncItConfig>,
+    enc_it: Rc<dyn EncIt>,
+    statement_reader: RefCell<Box<dyn EncItFileReader>>,
+    writer: Rc<RefCell<dyn Write>>,
+) -> Result<(), EncItError> {
+    let mut statement = String::new();
+    statement_reader
+        .borrow_mut()
+        .read_to_string(&mut statement)?;
+    let (friend_name, key) = enc_it.verify_transition(&statement)?;
+    let hex_key = EncItPEM::Hex(key.hex_pem()?);
+    config.set_friend_key(&friend_name, &hex_key)?.save()?;
+    writeln!(writer.borrow_mut(), "friend {} key updated", friend_name)?;
+    Ok(())
+}
+
 fn add_friend(
     arg_matches: &ArgMatches,
     config: Rc<dyn EncItConfig>,
     key_reader: RefCell<Box<dyn EncItFileReader>>,
 ) -> Result<(), EncItError> {
     let key = get_key(arg_matches, key_reader)?;
-    // check if is a valid public key
-    let _ = key.public_key()?;
+    save_friend(arg_matches, config, &key, EncItTrustLevel::Unverified)
+}
+
+fn save_friend(
+    arg_matches: &ArgMatches,
+    config: Rc<dyn EncItConfig>,
+    key: &EncItPEM,
+    trust: EncItTrustLevel,
+) -> Result<(), EncItError> {
+    // check if is a supported public key, the key ids are needed to find the friend
+    key.key_type()?;
+    key.thumbprint()?;
     let hex_hey = EncItPEM::Hex(key.hex_pem()?);
 
     let friend_name = arg_matches.value_of("name").unwrap();
@@ -29,7 +119,7 @@ fn add_friend(
     if friend_name.contains(' ') {
         return Err(InvalidCommand("friend name could not have spaces".into()));
     }
-    config.add_friend(friend_name, &hex_hey)?.save()
+    config.add_friend(friend_name, &hex_hey, trust)?.save()
 }
 
 #[cfg(test)]
@@ -39,11 +129,14 @@ mod tests {
     use std::sync::Arc;
 
     use mockall::predicate::eq;
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::nid::Nid;
     use openssl::rsa::Rsa;
 
     use crate::cmd::add_cmd::add_cmd;
     use crate::cmd::reader::EncItFileReader;
-    use crate::config::MockEncItConfig;
+    use crate::config::{EncItKeyType, MockEncItConfig};
+    use crate::enc::MockEncIt;
     use crate::{EncItConfig, EncItPEM};
 
     use super::*;
@@ -64,6 +157,23 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn add_friend_unsupported_key() {
+        let cmd = add_cmd("friend");
+        let matches =
+            cmd.get_matches_from(vec!["friend", "--name", "friend-p521", "--format", "pem"]);
+        let group = EcGroup::from_curve_name(Nid::SECP521R1).unwrap();
+        let pub_key_pem = EcKey::generate(&group)
+            .unwrap()
+            .public_key_to_pem()
+            .unwrap();
+        let pem_key = Box::leak(Box::new(pub_key_pem));
+        let key_reader: RefCell<Box<dyn EncItFileReader>> =
+            RefCell::new(Box::new(pem_key.as_slice()));
+        let cfg_mock: Rc<dyn EncItConfig> = Rc::new(MockEncItConfig::new());
+        assert!(add_friend(&matches, cfg_mock, key_reader).is_err());
+    }
+
     #[test]
     fn add_friend_hex() {
         let friend_name = "friend-hex-1";
@@ -100,6 +210,120 @@ mod tests {
         check_add_friend(friend_name, &matches, key_reader, expected_encit_pem);
     }
 
+    #[test]
+    fn add_friend_ed25519_pem() {
+        let friend_name = "friend-ed25519-1";
+        let cmd = add_cmd("friend");
+        let matches =
+            cmd.get_matches_from(vec!["friend", "--name", friend_name, "--format", "pem"]);
+        let priv_key = EncItKeyType::Ed25519.generate().unwrap();
+        let pub_key_pem = priv_key.public_key_to_pem().unwrap();
+        let expected_encit_pem = EncItPEM::Hex(hex::encode(&pub_key_pem));
+        let pem_key = Box::leak(Box::new(pub_key_pem));
+        let key_reader: RefCell<Box<dyn EncItFileReader>> =
+            RefCell::new(Box::new(pem_key.as_slice()));
+        check_add_friend(friend_name, &matches, key_reader, expected_encit_pem);
+    }
+
+    #[test]
+    fn add_friend_from_message_test() {
+        let friend_name = "friend-tofu-1";
+        let matches = add_friend_cmd().get_matches_from(vec![
+            "friend",
+            "--name",
+            friend_name,
+            "--from-message",
+            "msg.enc",
+        ]);
+        let priv_key = EncItKeyType::EcP256.generate().unwrap();
+        let expected_encit_pem = EncItPEM::Hex(hex::encode(priv_key.public_key_to_pem().unwrap()));
+        let sender_key: &'static EncItPEM = Box::leak(Box::new(expected_encit_pem.clone()));
+        let mut encit_mock = MockEncIt::new();
+        encit_mock
+            .expect_sender_key()
+            .withf(|message_param, identity_param| {
+                message_param == "encrypted" && identity_param.is_none()
+            })
+            .returning(move |_, _| Ok(sender_key.clone()));
+        let message_reader: RefCell<Box<dyn EncItFileReader>> =
+            RefCell::new(Box::new("encrypted".as_bytes()));
+
+        let mut cfg_mock = MockEncItConfig::new();
+        cfg_mock
+            .expect_add_friend()
+            .with(
+                eq(friend_name),
+                eq(expected_encit_pem),
+                eq(EncItTrustLevel::Tofu),
+            )
+            .returning(|_, _, _| {
+                let mut new_cfg = MockEncItConfig::new();
+                new_cfg.expect_save().returning(|| Ok(()));
+                Ok(Box::new(new_cfg))
+            });
+        add_friend_from_message(
+            &matches,
+            Rc::new(cfg_mock),
+            Rc::new(encit_mock),
+            message_reader,
+        )
+        .expect("add friend in error");
+    }
+
+    #[test]
+    fn add_friend_transition_test() -> Result<(), EncItError> {
+        let matches = add_friend_cmd().get_matches_from(vec!["friend", "--transition", "stmt"]);
+        assert_eq!(matches.value_of("transition"), Some("stmt"));
+        let priv_key = EncItKeyType::Ed25519.generate()?;
+        let new_key = EncItPEM::Hex(hex::encode(priv_key.public_key_to_pem()?));
+        let transition_key: &'static EncItPEM = Box::leak(Box::new(new_key.clone()));
+        let mut encit_mock = MockEncIt::new();
+        encit_mock
+            .expect_verify_transition()
+            .with(eq("statement"))
+            .returning(move |_| Ok(("friend-1".to_string(), transition_key.clone())));
+        let mut cfg_mock = MockEncItConfig::new();
+        cfg_mock
+            .expect_set_friend_key()
+            .with(eq("friend-1"), eq(new_key))
+            .times(1)
+            .returning(|_, _| {
+                let mut new_cfg = MockEncItConfig::new();
+                new_cfg.expect_save().times(1).returning(|| Ok(()));
+                Ok(Box::new(new_cfg))
+            });
+        let statement_reader: RefCell<Box<dyn EncItFileReader>> =
+            RefCell::new(Box::new("statement".as_bytes()));
+        let writer: Rc<RefCell<Vec<u8>>> = Rc::new(RefCell::new(Vec::new()));
+        add_friend_transition(
+            Rc::new(cfg_mock),
+            Rc::new(encit_mock),
+            statement_reader,
+            writer.clone(),
+        )?;
+        assert_eq!(
+            String::from_utf8(writer.borrow().to_vec())?,
+            "friend friend-1 key updated\n"
+        );
+        assert!(add_friend_cmd()
+            .get_matches_from_safe(vec!["friend", "--name", "friend-1", "--transition", "stmt"])
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn add_friend_from_message_with_key_file() {
+        let result = add_friend_cmd().get_matches_from_safe(vec![
+            "friend",
+            "--name",
+            "friend-1",
+            "--from-message",
+            "msg.enc",
+            "key.pem",
+        ]);
+        assert!(result.is_err());
+    }
+
     fn check_add_friend(
         friend_name: &'static str,
         matches: &ArgMatches,
@@ -109,8 +333,12 @@ mod tests {
         let mut cfg_mock = MockEncItConfig::new();
         cfg_mock
             .expect_add_friend()
-            .with(eq(friend_name), eq(expected_encit_pem))
-            .returning(|_, _| {
+            .with(
+                eq(friend_name),
+                eq(expected_encit_pem),
+                eq(EncItTrustLevel::Unverified),
+            )
+            .returning(|_, _, _| {
                 let mut new_cfg = MockEncItConfig::new();
                 new_cfg.expect_save().returning(|| Ok(()));
                 Ok(Box::new(new_cfg))
@@ -0,0 +1,401 @@
+use crate::cmd::file_metadata::safe_file_name;
+use crate::cmd::reader::EncItFileReader;
+use crate::EncItError;
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::collections::VecDeque;
+use std::fs::{create_dir, read_dir, symlink_metadata, File, Permissions};
+use std::io::{copy, Cursor, Read, Take, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+use tempfile::NamedTempFile;
+
+/// content type of the file metadata of an encrypted directory
+pub const ARCHIVE_CONTENT_TYPE: &str = "application/x-encit-archive";
+/// first bytes of an archive, followed by the big endian length of the JSON manifest,
+/// the manifest and the content of the files in the manifest order
+const ARCHIVE_MAGIC: &[u8] = b"ENCIT-ARCHIVE-1\n";
+const MANIFEST_LEN_SIZE: usize = 4;
+const MAX_MANIFEST_LEN: usize = 64 * 1024 * 1024;
+const PATH_SEPARATOR: char = '/';
+/// setuid, setgid and sticky bits are never restored
+const MODE_MASK: u32 = 0o777;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum EncItEntryType {
+    File,
+    Directory,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+struct EncItManifestEntry {
+    /// relative path, '/' separated, the first component is the archived directory
+    path: String,
+    #[serde(rename = "type")]
+    entry_type: EncItEntryType,
+    #[serde(default)]
+    size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mode: Option<u32>,
+    /// modification time, seconds since the unix epoch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mtime: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct EncItManifest {
+    entries: Vec<EncItManifestEntry>,
+}
+
+/// Reads a directory as an archive: the manifest is built upfront, the files are opened
+/// one at a time while reading, so the archive can be encrypted as a stream.
+pub struct EncItArchiveReader {
+    header: Cursor<Vec<u8>>,
+    files: VecDeque<(PathBuf, u64)>,
+    current: Option<(PathBuf, Take<File>)>,
+}
+
+impl EncItArchiveReader {
+    pub fn new(dir: &Path) -> Result<Self, EncItError> {
+        let name = dir
+            .canonicalize()?
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map(|name| name.to_string())
+            .ok_or_else(|| EncItError::InvalidFileName(dir.display().to_string()))?;
+        let mut entries = Vec::new();
+        let mut files = VecDeque::new();
+        add_entries(dir, &name, &mut entries, &mut files)?;
+        let manifest = serde_json::to_vec(&EncItManifest { entries })?;
+        let mut header = ARCHIVE_MAGIC.to_vec();
+        header.extend_from_slice(&(manifest.len() as u32).to_be_bytes());
+        header.extend_from_slice(&manifest);
+        Ok(EncItArchiveReader {
+            header: Cursor::new(header),
+            files,
+            current: None,
+        })
+    }
+}
+
+/// the entries are sorted by name, symbolic links and special files are skipped
+fn add_entries(
+    path: &Path,
+    archive_path: &str,
+    entries: &mut Vec<EncItManifestEntry>,
+    files: &mut VecDeque<(PathBuf, u64)>,
+) -> Result<(), EncItError> {
+    let metadata = symlink_metadata(path)?;
+    let mtime = metadata
+        .modified()
+        .ok()
+        .and_then(|mtime| mtime.duration_since(UNIX_EPOCH).ok())
+        .map(|mtime| mtime.as_secs());
+    let mode = Some(metadata.permissions().mode() & MODE_MASK);
+    if metadata.is_dir() {
+        entries.push(EncItManifestEntry {
+            path: archive_path.to_string(),
+            entry_type: EncItEntryType::Directory,
+            size: 0,
+            mode,
+            mtime,
+        });
+        let mut children = read_dir(path)?
+            .map(|child| child.map(|child| child.path()))
+            .collect::<Result<Vec<PathBuf>, std::io::Error>>()?;
+        children.sort();
+        for child in children {
+            let child_name = child
+                .file_name()
+                .and_then(|name| name.to_str())
+                .ok_or_else(|| EncItError::InvalidFileName(child.display().to_string()))?;
+            add_entries(
+                &child,
+                &format!("{}{}{}", archive_path, PATH_SEPARATOR, child_name),
+                entries,
+                files,
+            )?;
+        }
+    } else if metadata.is_file() {
+        entries.push(EncItManifestEntry {
+            path: archive_path.to_string(),
+            entry_type: EncItEntryType::File,
+            size: metadata.len(),
+            mode,
+            mtime,
+        });
+        files.push_back((path.to_path_buf(), metadata.len()));
+    } else {
+        eprintln!("Warning: {} is not a regular file, skipped", path.display());
+    }
+    Ok(())
+}
+
+impl Read for EncItArchiveReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.header.read(buf)?;
+        if read > 0 {
+            return Ok(read);
+        }
+        loop {
+            if let Some((path, file)) = self.current.as_mut() {
+                let read = file.read(buf)?;
+                if read > 0 {
+                    return Ok(read);
+                }
+                // the manifest size is the content length, a file cannot shrink meanwhile
+                if file.limit() > 0 {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::UnexpectedEof,
+                        format!("{} changed while reading it", path.display()),
+                    ));
+                }
+            }
+            match self.files.pop_front() {
+                Some((path, size)) => {
+                    let file = File::open(&path)?.take(size);
+                    self.current = Some((path, file));
+                }
+                None => return Ok(0),
+            }
+        }
+    }
+}
+
+impl EncItFileReader for EncItArchiveReader {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// the archive paths are relative, every component has to be a plain name
+fn safe_path(output_dir: &Path, archive_path: &str) -> Result<PathBuf, EncItError> {
+    archive_path
+        .split(PATH_SEPARATOR)
+        .try_fold(output_dir.to_path_buf(), |path, component| {
+            safe_file_name(component)
+                .map(|component| path.join(component))
+                .map_err(|_| EncItError::InvalidFileName(format!("{:?}", archive_path)))
+        })
+}
+
+/// an existing directory is reused only if it is not a symbolic link
+fn create_archive_dir(path: &Path) -> Result<(), EncItError> {
+    match symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => Ok(()),
+        Ok(_) => Err(EncItError::InvalidFileName(format!(
+            "{} exists and it is not a directory",
+            path.display()
+        ))),
+        Err(_) => create_dir(path).map_err(|e| e.into()),
+    }
+}
+
+/// Extracts the archive in the output directory. The manifest is checked before writing
+/// anything: absolute paths and `..` are refused, the files are created only in the
+/// directories of the archive and existing files are never overwritten.
+pub fn extract_archive(
+    reader: &mut dyn Read,
+    output_dir: &Path,
+) -> Result<Vec<PathBuf>, EncItError> {
+    let mut magic = vec![0u8; ARCHIVE_MAGIC.len()];
+    reader.read_exact(&mut magic)?;
+    if magic != ARCHIVE_MAGIC {
+        return Err(EncItError::DecodeError(
+            "the message is not an encit archive".to_string(),
+        ));
+    }
+    let mut manifest_len = [0u8; MANIFEST_LEN_SIZE];
+    reader.read_exact(&mut manifest_len)?;
+    let manifest_len = u32::from_be_bytes(manifest_len) as usize;
+    if manifest_len > MAX_MANIFEST_LEN {
+        return Err(EncItError::DecodeError(
+            "invalid archive manifest".to_string(),
+        ));
+    }
+    let mut manifest = vec![0u8; manifest_len];
+    reader.read_exact(&mut manifest)?;
+    let manifest: EncItManifest = serde_json::from_slice(&manifest)?;
+
+    let mut directories: Vec<PathBuf> = Vec::new();
+    let mut paths = Vec::with_capacity(manifest.entries.len());
+    for entry in &manifest.entries {
+        let path = safe_path(output_dir, &entry.path)?;
+        // the parent of an entry is the output directory or a directory of the archive
+        let parent = path.parent().unwrap_or(output_dir);
+        if parent != output_dir && !directories.iter().any(|dir| dir == parent) {
+            return Err(EncItError::InvalidFileName(format!(
+                "{:?} is not in an archive directory",
+                entry.path
+            )));
+        }
+        if entry.entry_type == EncItEntryType::Directory {
+            directories.push(path.clone());
+        }
+        paths.push(path);
+    }
+
+    for (entry, path) in manifest.entries.iter().zip(paths.iter()) {
+        match entry.entry_type {
+            EncItEntryType::Directory => create_archive_dir(path)?,
+            EncItEntryType::File => {
+                // an incomplete file is removed with its temporary file
+                let mut temp_file = NamedTempFile::new_in(path.parent().unwrap_or(output_dir))?;
+                let written = copy(&mut reader.take(entry.size), &mut temp_file)?;
+                if written != entry.size {
+                    return Err(EncItError::DecodeError(format!(
+                        "truncated archive, {} is incomplete",
+                        entry.path
+                    )));
+                }
+                temp_file.flush()?;
+                let file = temp_file.as_file();
+                if let Some(mode) = entry.mode {
+                    file.set_permissions(Permissions::from_mode(mode & MODE_MASK))?;
+                }
+                if let Some(mtime) = entry.mtime {
+                    file.set_modified(UNIX_EPOCH + Duration::from_secs(mtime))?;
+                }
+                temp_file
+                    .persist_noclobber(path)
+                    .map_err(|e| EncItError::IoError(format!("{}: {}", path.display(), e.error)))?;
+            }
+        }
+    }
+    if reader.read(&mut [0u8; 1])? > 0 {
+        return Err(EncItError::DecodeError(
+            "unexpected data after the archive files".to_string(),
+        ));
+    }
+    // the directory permissions are restored after their content, deepest first
+    for (entry, path) in manifest.entries.iter().zip(paths.iter()).rev() {
+        if let (EncItEntryType::Directory, Some(mode)) = (entry.entry_type, entry.mode) {
+            std::fs::set_permissions(path, Permissions::from_mode(mode & MODE_MASK))?;
+        }
+    }
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{read, write};
+    use std::os::unix::fs::symlink;
+
+    fn archive(entries: Vec<EncItManifestEntry>, content: &[u8]) -> Vec<u8> {
+        let manifest = serde_json::to_vec(&EncItManifest { entries }).unwrap();
+        let mut archive = ARCHIVE_MAGIC.to_vec();
+        archive.extend_from_slice(&(manifest.len() as u32).to_be_bytes());
+        archive.extend_from_slice(&manifest);
+        archive.extend_from_slice(content);
+        archive
+    }
+
+    fn file_entry(path: &str, size: u64) -> EncItManifestEntry {
+        EncItManifestEntry {
+            path: path.to_string(),
+            entry_type: EncItEntryType::File,
+            size,
+            mode: None,
+            mtime: None,
+        }
+    }
+
+    #[test]
+    fn archive_extract() -> Result<(), EncItError> {
+        let source = tempfile::tempdir()?;
+        let reports = source.path().join("reports");
+        create_dir(&reports)?;
+        create_dir(reports.join("q1"))?;
+        write(reports.join("summary.txt"), b"summary")?;
+        write(reports.join("q1").join("sales.csv"), b"month,total\n")?;
+        std::fs::set_permissions(reports.join("summary.txt"), Permissions::from_mode(0o640))?;
+        symlink("/etc/passwd", reports.join("passwd"))?;
+
+        let mut archive = Vec::new();
+        EncItArchiveReader::new(&reports)?.read_to_end(&mut archive)?;
+        assert!(archive.starts_with(ARCHIVE_MAGIC));
+
+        let output = tempfile::tempdir()?;
+        let paths = extract_archive(&mut archive.as_slice(), output.path())?;
+        assert_eq!(paths.len(), 4);
+        let extracted = output.path().join("reports");
+        assert_eq!(read(extracted.join("summary.txt"))?, b"summary");
+        assert_eq!(
+            read(extracted.join("q1").join("sales.csv"))?,
+            b"month,total\n"
+        );
+        assert_eq!(
+            symlink_metadata(extracted.join("summary.txt"))?
+                .permissions()
+                .mode()
+                & MODE_MASK,
+            0o640
+        );
+        assert!(!extracted.join("passwd").exists());
+
+        // existing files are not overwritten
+        assert!(extract_archive(&mut archive.as_slice(), output.path()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn reject_unsafe_paths() -> Result<(), EncItError> {
+        let parent = tempfile::tempdir()?;
+        let output = parent.path().join("out");
+        create_dir(&output)?;
+        for path in ["../escaped", "/tmp/escaped", "a/../../escaped", "", "a//b"] {
+            let archive = archive(vec![file_entry(path, 1)], b"x");
+            let result = extract_archive(&mut archive.as_slice(), &output);
+            assert!(
+                matches!(result, Err(EncItError::InvalidFileName(_))),
+                "{:?} accepted",
+                path
+            );
+        }
+        // files only in the directories declared by the archive
+        let archive = archive(vec![file_entry("undeclared/file", 1)], b"x");
+        assert!(extract_archive(&mut archive.as_slice(), &output).is_err());
+        assert_eq!(read_dir(&output)?.count(), 0);
+        assert!(!parent.path().join("escaped").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn reject_symlink_directory() -> Result<(), EncItError> {
+        let output = tempfile::tempdir()?;
+        let target = tempfile::tempdir()?;
+        symlink(target.path(), output.path().join("reports"))?;
+        let archive = archive(
+            vec![
+                EncItManifestEntry {
+                    path: "reports".to_string(),
+                    entry_type: EncItEntryType::Directory,
+                    size: 0,
+                    mode: None,
+                    mtime: None,
+                },
+                file_entry("reports/file", 1),
+            ],
+            b"x",
+        );
+        assert!(extract_archive(&mut archive.as_slice(), output.path()).is_err());
+        assert_eq!(read_dir(target.path())?.count(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn truncated_archive() -> Result<(), EncItError> {
+        let output = tempfile::tempdir()?;
+        let archive = archive(vec![file_entry("file", 10)], b"short");
+        assert!(matches!(
+            extract_archive(&mut archive.as_slice(), output.path()),
+            Err(EncItError::DecodeError(_))
+        ));
+        // no partially written file is left behind
+        assert_eq!(read_dir(output.path())?.count(), 0);
+        Ok(())
+    }
+}
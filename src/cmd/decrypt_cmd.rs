@@ -1,10 +1,21 @@
+use crate::armor::dearmor;
+use crate::cmd::archive::extract_archive;
+use crate::cmd::file_metadata::{output_file_path, persist_output_file};
+use crate::cmd::password::{EncItPasswordReader, TtyPasswordReader};
 use crate::cmd::reader::{get_file_reader, EncItFileReader};
-use crate::enc::{EncIt, EncItImpl};
+use crate::config::EncItTrustLevel;
+use crate::enc::{
+    is_password_encrypted, EncIt, EncItDecryptOptions, EncItImpl, EncItMessage, EncItPassword,
+};
+use crate::seen::EncItSeenMessagesImpl;
+use crate::stream::{decrypt_stream, read_stream_header, STREAM_MAGIC};
 use crate::{EncItConfig, EncItError};
 use clap::{App, Arg, ArgMatches, SubCommand};
 use std::cell::RefCell;
-use std::io::{stdout, Read, Write};
+use std::io::{stdout, BufReader, Cursor, Read, Seek, Write};
+use std::path::Path;
 use std::rc::Rc;
+use tempfile::{tempfile_in, NamedTempFile};
 
 pub fn decrypt_cmd<'a>() -> App<'a, 'a> {
     SubCommand::with_name("decrypt")
@@ -17,6 +28,32 @@ pub fn decrypt_cmd<'a>() -> App<'a, 'a> {
                 .help("Identity name (has to be present in the encit configuration file)"),
         )
         .arg(Arg::with_name("json").long("json"))
+        .arg(
+            Arg::with_name("output-dir")
+                .long("output-dir")
+                .takes_value(true)
+                .conflicts_with("json")
+                .help(
+                    "Recreate the sender's file (name, mode, modification time) in this directory",
+                ),
+        )
+        .arg(
+            Arg::with_name("extract")
+                .long("extract")
+                .takes_value(true)
+                .conflicts_with_all(&["json", "output-dir"])
+                .help("Extract the encrypted directory in this directory"),
+        )
+        .arg(
+            Arg::with_name("allow-expired")
+                .long("allow-expired")
+                .help("Decrypt the message even if it is expired"),
+        )
+        .arg(
+            Arg::with_name("allow-unknown-sender")
+                .long("allow-unknown-sender")
+                .help("Decrypt messages from senders not in the friends list (not verified)"),
+        )
         .arg(
             Arg::with_name("file")
                 .takes_value(true)
@@ -28,10 +65,11 @@ pub fn decrypt_exec(
     cmd_matches: &ArgMatches,
     config: Rc<dyn EncItConfig>,
 ) -> Result<(), EncItError> {
-    let enc_it = Rc::new(EncItImpl::new(config));
+    let seen_messages = Rc::new(EncItSeenMessagesImpl::new(Path::new(config.path())));
+    let enc_it = Rc::new(EncItImpl::new(config).with_seen_messages(seen_messages));
     let reader = RefCell::new(get_file_reader(cmd_matches, "file")?);
     let rc_stdout: Rc<RefCell<dyn Write>> = Rc::new(RefCell::new(stdout()));
-    decrypt(cmd_matches, enc_it, reader, rc_stdout)
+    decrypt(cmd_matches, enc_it, reader, rc_stdout, &TtyPasswordReader)
 }
 
 fn decrypt(
@@ -39,29 +77,169 @@ fn decrypt(
     enc_it: Rc<dyn EncIt>,
     reader: RefCell<Box<dyn EncItFileReader>>,
     writer: Rc<RefCell<dyn Write>>,
+    password_reader: &dyn EncItPasswordReader,
 ) -> Result<(), EncItError> {
     let identity = cmd_matches.value_of("identity");
+    let output_dir = cmd_matches.value_of("output-dir").map(Path::new);
+    let extract_dir = cmd_matches.value_of("extract").map(Path::new);
+    let mut options = EncItDecryptOptions {
+        allow_expired: cmd_matches.is_present("allow-expired"),
+        allow_unknown_sender: cmd_matches.is_present("allow-unknown-sender"),
+        password: None,
+        defer_seen: false,
+    };
+    // the password is asked only if the message key is derived from a password
+    let mut read_password = |message: &str| -> Result<(), EncItError> {
+        if is_password_encrypted(message) {
+            options.password = Some(EncItPassword::new(
+                password_reader.read_password("Password: ")?,
+            ));
+        }
+        Ok(())
+    };
+    let mut reader = reader.borrow_mut();
+    let mut magic = Vec::with_capacity(STREAM_MAGIC.len());
+    (&mut *reader)
+        .take(STREAM_MAGIC.len() as u64)
+        .read_to_end(&mut magic)?;
+    if magic == STREAM_MAGIC {
+        if cmd_matches.is_present("json") {
+            return Err(EncItError::InvalidCommand(
+                "json output is not available for stream messages".to_string(),
+            ));
+        }
+        let mut reader = BufReader::new(&mut *reader);
+        let jwe_header = read_stream_header(&mut reader)?;
+        read_password(&jwe_header)?;
+        // the file name is known only after the header decryption, the chunks are written
+        // to a temporary file of the output directory
+        let mut temp_file = output_dir.map(NamedTempFile::new_in).transpose()?;
+        let mut archive_file = extract_dir.map(tempfile_in).transpose()?;
+        let mut writer = writer.borrow_mut();
+        let header_message = decrypt_stream(
+            enc_it.as_ref(),
+            &jwe_header,
+            identity,
+            &options,
+            &mut reader,
+            match (temp_file.as_mut(), archive_file.as_mut()) {
+                (Some(temp_file), _) => temp_file,
+                (_, Some(archive_file)) => archive_file,
+                _ => &mut *writer,
+            },
+        )?;
+        warn(&header_message);
+        if let (Some(output_dir), Some(temp_file)) = (output_dir, temp_file) {
+            write_output_file(output_dir, &header_message, temp_file)?;
+        }
+        if let (Some(extract_dir), Some(mut archive_file)) = (extract_dir, archive_file) {
+            archive_file.rewind()?;
+            extract(&mut BufReader::new(archive_file), extract_dir)?;
+        }
+        return Ok(());
+    }
     let mut encrypted_message = String::new();
-    reader.borrow_mut().read_to_string(&mut encrypted_message)?;
+    Cursor::new(magic)
+        .chain(&mut *reader)
+        .read_to_string(&mut encrypted_message)?;
+    // armored, compact or JSON serialization
+    let encrypted_message = dearmor(&encrypted_message)?;
+    read_password(&encrypted_message)?;
 
-    let decrypted_message = enc_it.decrypt(&encrypted_message, identity)?;
+    let decrypted_message = enc_it.decrypt(&encrypted_message, identity, &options)?;
     let mut writer = writer.borrow_mut();
-    if cmd_matches.is_present("json") {
+    if let Some(output_dir) = output_dir {
+        warn(&decrypted_message);
+        // the name is checked before writing anything
+        output_file_path(output_dir, decrypted_message.file())?;
+        let mut temp_file = NamedTempFile::new_in(output_dir)?;
+        temp_file.write_all(decrypted_message.payload())?;
+        write_output_file(output_dir, &decrypted_message, temp_file)?;
+    } else if let Some(extract_dir) = extract_dir {
+        warn(&decrypted_message);
+        extract(&mut decrypted_message.payload(), extract_dir)?;
+    } else if cmd_matches.is_present("json") {
         writer.write_all(serde_json::to_vec(&decrypted_message)?.as_slice())?;
     } else {
-        let payload = base64::decode(decrypted_message.payload())?;
-        writer.write_all(payload.as_slice())?;
+        warn(&decrypted_message);
+        writer.write_all(decrypted_message.payload())?;
     }
     Ok(())
 }
 
+fn write_output_file(
+    output_dir: &Path,
+    message: &EncItMessage,
+    temp_file: NamedTempFile,
+) -> Result<(), EncItError> {
+    let path = output_file_path(output_dir, message.file())?;
+    temp_file.as_file().sync_all()?;
+    persist_output_file(temp_file, &path, message.file())?;
+    eprintln!("Decrypted to {}", path.display());
+    Ok(())
+}
+
+fn extract(reader: &mut dyn Read, extract_dir: &Path) -> Result<(), EncItError> {
+    let paths = extract_archive(reader, extract_dir)?;
+    eprintln!(
+        "Extracted {} files and directories to {}",
+        paths.len(),
+        extract_dir.display()
+    );
+    Ok(())
+}
+
+fn warn(message: &EncItMessage) {
+    if let (false, Some(sender_fingerprint)) = (message.signed(), message.sender_fingerprint()) {
+        eprintln!(
+            "Warning: unknown sender with fingerprint {}, the signature is not checked without the embedded key",
+            sender_fingerprint
+        );
+    } else if !message.signed() {
+        eprintln!("Warning: the message is not signed, the sender is unknown");
+    } else if !message.verified() && message.sender().is_none() {
+        eprintln!(
+            "Warning: unknown sender with fingerprint {}, the signature is not verified",
+            message.sender_fingerprint().unwrap_or_default()
+        );
+    }
+    match (message.sender(), message.sender_trust()) {
+        (Some(sender), Some(EncItTrustLevel::Revoked)) => {
+            eprintln!(
+                "Warning: the key of {} has been revoked, the signature is not verified",
+                sender
+            )
+        }
+        (Some(sender), Some(EncItTrustLevel::Unverified | EncItTrustLevel::Tofu)) => eprintln!(
+            "Warning: the key of {} is not verified, compare `encit fingerprint {}` with them then run `encit verify friend {} --fingerprint ...`",
+            sender, sender, sender
+        ),
+        _ => {}
+    }
+    if let Some(forwarder_fingerprint) = message.forwarder_fingerprint() {
+        match message.forwarded_by() {
+            Some(forwarded_by) => eprintln!("Forwarded by {}", forwarded_by),
+            None => eprintln!(
+                "Warning: forwarded by an unknown friend with fingerprint {}, the message is not verified",
+                forwarder_fingerprint
+            ),
+        }
+    }
+    if message.replayed() {
+        eprintln!("Warning: the message has already been decrypted");
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::enc::{EncItMessage, MockEncIt};
+    use crate::cmd::archive::EncItArchiveReader;
+    use crate::cmd::password::MockEncItPasswordReader;
+    use crate::enc::{EncItFileMetadata, EncItMessage, MockEncIt};
     use crate::EncItError;
     use rand::distributions::Alphanumeric;
     use rand::{thread_rng, Rng};
+    use std::os::unix::fs::PermissionsExt;
 
     #[test]
     fn auto_decrypt() -> Result<(), EncItError> {
@@ -78,14 +256,15 @@ mod tests {
         let mut encit_mock = MockEncIt::new();
         encit_mock
             .expect_decrypt()
-            .withf(move |jwe_param, opt_identity| jwe_param == jwe_message && *opt_identity == None)
-            .returning(|_, _| {
-                let base64_payload = base64::encode("payload");
+            .withf(move |jwe_param, opt_identity, _| {
+                jwe_param == jwe_message && opt_identity.is_none()
+            })
+            .returning(|_, _, _| {
                 Ok(EncItMessage::new(
-                    "sender".to_string(),
+                    Some("sender".to_string()),
                     "receiver".to_string(),
                     None,
-                    base64_payload,
+                    b"payload".to_vec(),
                     true,
                 ))
             });
@@ -93,7 +272,13 @@ mod tests {
         let reader = RefCell::new(Box::new(jwe_message2.as_bytes()));
 
         let writer: Rc<RefCell<Vec<u8>>> = Rc::new(RefCell::new(Vec::new()));
-        decrypt(&cmd_matches, rc_encit_mock, reader, writer.clone())?;
+        decrypt(
+            &cmd_matches,
+            rc_encit_mock,
+            reader,
+            writer.clone(),
+            &MockEncItPasswordReader::new(),
+        )?;
 
         let result = String::from_utf8(writer.borrow().to_vec())?;
         assert_eq!(result, "payload");
@@ -115,16 +300,15 @@ mod tests {
         let mut encit_mock = MockEncIt::new();
         encit_mock
             .expect_decrypt()
-            .withf(move |jwe_param, opt_identity| {
+            .withf(move |jwe_param, opt_identity, _| {
                 jwe_param == jwe_message && *opt_identity == Some("identity-1")
             })
-            .returning(|_, _| {
-                let base64_payload = base64::encode("payload");
+            .returning(|_, _, _| {
                 Ok(EncItMessage::new(
-                    "sender".to_string(),
+                    Some("sender".to_string()),
                     "receiver".to_string(),
                     None,
-                    base64_payload,
+                    b"payload".to_vec(),
                     true,
                 ))
             });
@@ -132,7 +316,13 @@ mod tests {
         let reader = RefCell::new(Box::new(jwe_message2.as_bytes()));
 
         let writer: Rc<RefCell<Vec<u8>>> = Rc::new(RefCell::new(Vec::new()));
-        decrypt(&cmd_matches, rc_encit_mock, reader, writer.clone())?;
+        decrypt(
+            &cmd_matches,
+            rc_encit_mock,
+            reader,
+            writer.clone(),
+            &MockEncItPasswordReader::new(),
+        )?;
 
         let result = String::from_utf8(writer.borrow().to_vec())?;
         assert_eq!(result, "payload");
@@ -151,12 +341,11 @@ mod tests {
             .collect();
         let jwe_message2 = Box::leak(Box::new(jwe_message.clone()));
 
-        let base64_payload = base64::encode("payload");
         let expected_message = EncItMessage::new(
-            "sender".to_string(),
+            Some("sender".to_string()),
             "receiver".to_string(),
             None,
-            base64_payload,
+            b"payload".to_vec(),
             true,
         );
         let mock_output = Box::leak(Box::new(expected_message.clone()));
@@ -164,16 +353,174 @@ mod tests {
         let mut encit_mock = MockEncIt::new();
         encit_mock
             .expect_decrypt()
-            .withf(move |jwe_param, opt_identity| jwe_param == jwe_message && *opt_identity == None)
-            .returning(move |_, _| Ok((*mock_output).clone()));
+            .withf(move |jwe_param, opt_identity, _| {
+                jwe_param == jwe_message && opt_identity.is_none()
+            })
+            .returning(move |_, _, _| Ok((*mock_output).clone()));
         let rc_encit_mock = Rc::new(encit_mock);
         let reader = RefCell::new(Box::new(jwe_message2.as_bytes()));
 
         let writer: Rc<RefCell<Vec<u8>>> = Rc::new(RefCell::new(Vec::new()));
-        decrypt(&cmd_matches, rc_encit_mock, reader, writer.clone())?;
+        decrypt(
+            &cmd_matches,
+            rc_encit_mock,
+            reader,
+            writer.clone(),
+            &MockEncItPasswordReader::new(),
+        )?;
 
         let result = String::from_utf8(writer.borrow().to_vec())?;
         assert_eq!(result, json_expected_message);
         Ok(())
     }
+
+    #[test]
+    fn password_decrypt() -> Result<(), EncItError> {
+        let cmd_matches = decrypt_cmd().get_matches_from(vec!["decrypt"]);
+        let header = base64::encode_config(
+            r#"{"alg":"PBES2-HS512+A256KW","enc":"A256CBC-HS512"}"#,
+            base64::URL_SAFE_NO_PAD,
+        );
+        let jwe_message = Box::leak(Box::new(format!("{}.key.iv.ciphertext.tag", header)));
+        let mut encit_mock = MockEncIt::new();
+        encit_mock
+            .expect_decrypt()
+            .withf(|_, _, options_param| {
+                options_param.password == Some(EncItPassword::new("s3cret".to_string()))
+            })
+            .returning(|_, _, _| {
+                Ok(EncItMessage::new(
+                    None,
+                    String::new(),
+                    None,
+                    b"payload".to_vec(),
+                    false,
+                ))
+            });
+        let mut password_reader = MockEncItPasswordReader::new();
+        password_reader
+            .expect_read_password()
+            .times(1)
+            .returning(|_| Ok("s3cret".to_string()));
+        let reader = RefCell::new(Box::new(jwe_message.as_bytes()));
+        let writer: Rc<RefCell<Vec<u8>>> = Rc::new(RefCell::new(Vec::new()));
+        decrypt(
+            &cmd_matches,
+            Rc::new(encit_mock),
+            reader,
+            writer.clone(),
+            &password_reader,
+        )?;
+        assert_eq!(String::from_utf8(writer.borrow().to_vec())?, "payload");
+        Ok(())
+    }
+
+    fn decrypt_to_dir(output_dir: &Path, file: EncItFileMetadata) -> Result<(), EncItError> {
+        let cmd_matches = decrypt_cmd().get_matches_from(vec![
+            "decrypt",
+            "--output-dir",
+            output_dir.to_str().unwrap(),
+        ]);
+        let mut encit_mock = MockEncIt::new();
+        encit_mock.expect_decrypt().returning(move |_, _, _| {
+            Ok(EncItMessage::new(
+                Some("sender".to_string()),
+                "receiver".to_string(),
+                None,
+                b"payload".to_vec(),
+                true,
+            )
+            .with_file(file.clone()))
+        });
+        let reader = RefCell::new(Box::new(&b"jwe"[..]));
+        let writer: Rc<RefCell<Vec<u8>>> = Rc::new(RefCell::new(Vec::new()));
+        decrypt(
+            &cmd_matches,
+            Rc::new(encit_mock),
+            reader,
+            writer.clone(),
+            &MockEncItPasswordReader::new(),
+        )?;
+        assert!(writer.borrow().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn output_dir_decrypt() -> Result<(), EncItError> {
+        let output_dir = tempfile::tempdir()?;
+        decrypt_to_dir(
+            output_dir.path(),
+            EncItFileMetadata {
+                name: Some("notes.txt".to_string()),
+                mode: Some(0o600),
+                ..Default::default()
+            },
+        )?;
+        let path = output_dir.path().join("notes.txt");
+        assert_eq!(std::fs::read(&path)?, b"payload");
+        assert_eq!(
+            std::fs::metadata(&path)?.permissions().mode() & 0o777,
+            0o600
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn output_dir_path_traversal() -> Result<(), EncItError> {
+        let parent_dir = tempfile::tempdir()?;
+        let output_dir = parent_dir.path().join("out");
+        std::fs::create_dir(&output_dir)?;
+        let result = decrypt_to_dir(
+            &output_dir,
+            EncItFileMetadata {
+                name: Some("../escaped".to_string()),
+                ..Default::default()
+            },
+        );
+        assert!(matches!(result, Err(EncItError::InvalidFileName(_))));
+        assert!(!parent_dir.path().join("escaped").exists());
+        assert_eq!(std::fs::read_dir(&output_dir)?.count(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn extract_decrypt() -> Result<(), EncItError> {
+        let source = tempfile::tempdir()?;
+        let reports = source.path().join("reports");
+        std::fs::create_dir(&reports)?;
+        std::fs::write(reports.join("summary.txt"), b"summary")?;
+        let mut archive = Vec::new();
+        EncItArchiveReader::new(&reports)?.read_to_end(&mut archive)?;
+
+        let extract_dir = tempfile::tempdir()?;
+        let cmd_matches = decrypt_cmd().get_matches_from(vec![
+            "decrypt",
+            "--extract",
+            extract_dir.path().to_str().unwrap(),
+        ]);
+        let mut encit_mock = MockEncIt::new();
+        encit_mock.expect_decrypt().returning(move |_, _, _| {
+            Ok(EncItMessage::new(
+                Some("sender".to_string()),
+                "receiver".to_string(),
+                None,
+                archive.clone(),
+                true,
+            ))
+        });
+        let writer: Rc<RefCell<Vec<u8>>> = Rc::new(RefCell::new(Vec::new()));
+        decrypt(
+            &cmd_matches,
+            Rc::new(encit_mock),
+            RefCell::new(Box::new(&b"jwe"[..])),
+            writer.clone(),
+            &MockEncItPasswordReader::new(),
+        )?;
+        assert!(writer.borrow().is_empty());
+        assert_eq!(
+            std::fs::read(extract_dir.path().join("reports").join("summary.txt"))?,
+            b"summary"
+        );
+        Ok(())
+    }
 }
@@ -1,12 +1,24 @@
-use crate::cmd::reader::{get_file_reader, EncItFileReader};
-use crate::enc::{EncIt, EncItImpl};
+use crate::armor::{armor, ARMOR_COMMENT_HEADER, ARMOR_VERSION_HEADER};
+use crate::cmd::file_metadata::read_file_metadata;
+use crate::cmd::password::{
+    confirm_unverified_friends, read_new_password, EncItPasswordReader, TtyConfirmReader,
+    TtyPasswordReader,
+};
+use crate::cmd::reader::{get_file_or_dir_reader, EncItFileReader};
+use crate::compression::EncItCompression;
+use crate::enc::{EncIt, EncItEncryptOptions, EncItImpl, PBES2_MIN_ITERATIONS};
+use crate::qr::render_qr_frames;
+use crate::stream::encrypt_stream;
 use crate::{EncItConfig, EncItError};
 use clap::{App, Arg, ArgMatches, SubCommand};
 use log::debug;
 use std::cell::RefCell;
 use std::io::{stdout, Read, Write};
+use std::path::Path;
 use std::rc::Rc;
 
+const QR_MESSAGE_TITLE: &str = "encit message";
+
 pub fn encrypt_cmd<'a>() -> App<'a, 'a> {
     SubCommand::with_name("encrypt")
         .about("encrypt a file/text")
@@ -14,17 +26,52 @@ pub fn encrypt_cmd<'a>() -> App<'a, 'a> {
             Arg::with_name("identity")
                 .long("identity")
                 .short("i")
-                .required(true)
+                .required_unless_one(&["anonymous", "password"])
                 .takes_value(true)
                 .help("Identity name (has to be present in the encit configuration file)"),
         )
+        .arg(
+            Arg::with_name("anonymous")
+                .long("anonymous")
+                .conflicts_with("identity")
+                .help("Encrypt without sender identity, the message is not signed"),
+        )
         .arg(
             Arg::with_name("friend")
                 .long("friend")
                 .short("f")
-                .required(true)
+                .required_unless("password")
+                .conflicts_with("password")
                 .takes_value(true)
-                .help("Friend name (has to be present in the encit configuration file)"),
+                .multiple(true)
+                .number_of_values(1)
+                .help(
+                    "Friend name (has to be present in the encit configuration file), repeatable",
+                ),
+        )
+        .arg(
+            Arg::with_name("password")
+                .long("password")
+                .help("Encrypt with a password (PBES2) instead of the friend keys"),
+        )
+        .arg(
+            Arg::with_name("password-iterations")
+                .long("password-iterations")
+                .takes_value(true)
+                .requires("password")
+                .help("PBES2 iterations [default: 210000]"),
+        )
+        .arg(
+            Arg::with_name("hide-recipient")
+                .long("hide-recipient")
+                .conflicts_with("password")
+                .help("Omit the friend fingerprints, the friends find out by trial decryption"),
+        )
+        .arg(
+            Arg::with_name("confirm-unverified")
+                .long("confirm-unverified")
+                .conflicts_with("password")
+                .help("Ask for a confirmation before encrypting for a friend that is not verified"),
         )
         .arg(
             Arg::with_name("subject")
@@ -33,10 +80,69 @@ pub fn encrypt_cmd<'a>() -> App<'a, 'a> {
                 .takes_value(true)
                 .help("Message subject"),
         )
+        .arg(
+            Arg::with_name("expires-in")
+                .long("expires-in")
+                .takes_value(true)
+                .help("The recipient refuses the message after this duration (e.g. 12h, 7d)"),
+        )
+        .arg(
+            Arg::with_name("burn-after-reading")
+                .long("burn-after-reading")
+                .help("The recipient refuses to decrypt the message a second time"),
+        )
+        .arg(
+            Arg::with_name("embed-key")
+                .long("embed-key")
+                .conflicts_with("anonymous")
+                .help("Embed the identity public key in the message"),
+        )
+        .arg(
+            Arg::with_name("compress")
+                .long("compress")
+                .conflicts_with_all(&["auto-compress", "stream"])
+                .help("Compress the file with DEFLATE before the encryption"),
+        )
+        .arg(
+            Arg::with_name("auto-compress")
+                .long("auto-compress")
+                .conflicts_with("stream")
+                .help("Compress the file only if it shrinks enough (e.g. logs, CSV)"),
+        )
+        .arg(
+            Arg::with_name("metadata")
+                .long("metadata")
+                .requires("file")
+                .help("Add the file name, content type, size, modification time and mode"),
+        )
+        .arg(
+            Arg::with_name("armor")
+                .long("armor")
+                .conflicts_with("stream")
+                .help("Wrap the message in text lines between BEGIN/END ENCIT MESSAGE markers"),
+        )
+        .arg(
+            Arg::with_name("qr")
+                .long("qr")
+                .conflicts_with_all(&["armor", "stream"])
+                .help("Display the message as QR codes, split in numbered frames if needed"),
+        )
+        .arg(
+            Arg::with_name("comment")
+                .long("comment")
+                .takes_value(true)
+                .requires("armor")
+                .help("Comment header of the armored message (not encrypted)"),
+        )
+        .arg(
+            Arg::with_name("stream")
+                .long("stream")
+                .help("Encrypt the file in authenticated chunks using constant memory"),
+        )
         .arg(
             Arg::with_name("file")
                 .takes_value(true)
-                .help("file to encrypt"),
+                .help("file or directory to encrypt"),
         )
 }
 
@@ -44,10 +150,18 @@ pub fn encrypt_exec(
     cmd_matches: &ArgMatches,
     config: Rc<dyn EncItConfig>,
 ) -> Result<(), EncItError> {
+    if cmd_matches.is_present("confirm-unverified") {
+        let friends: Vec<&str> = cmd_matches
+            .values_of("friend")
+            .into_iter()
+            .flatten()
+            .collect();
+        confirm_unverified_friends(&friends, config.as_ref(), &TtyConfirmReader)?;
+    }
     let enc_it = Rc::new(EncItImpl::new(config));
-    let reader = RefCell::new(get_file_reader(cmd_matches, "file")?);
+    let reader = RefCell::new(get_file_or_dir_reader(cmd_matches, "file")?);
     let writer = Rc::new(RefCell::new(stdout()));
-    encrypt(cmd_matches, enc_it, reader, writer)
+    encrypt(cmd_matches, enc_it, reader, writer, &TtyPasswordReader)
 }
 
 fn encrypt(
@@ -55,18 +169,84 @@ fn encrypt(
     enc_it: Rc<dyn EncIt>,
     reader: RefCell<Box<dyn EncItFileReader>>,
     writer: Rc<RefCell<dyn Write>>,
+    password_reader: &dyn EncItPasswordReader,
 ) -> Result<(), EncItError> {
-    let identity = cmd_matches.value_of("identity").unwrap();
-    let friend = cmd_matches.value_of("friend").unwrap();
-    let subject = cmd_matches.value_of("subject");
+    let identity = cmd_matches.value_of("identity");
+    let friends: Vec<&str> = cmd_matches
+        .values_of("friend")
+        .map(|friends| friends.collect())
+        .unwrap_or_default();
+    let password_iterations = cmd_matches
+        .value_of("password-iterations")
+        .map(|iterations| iterations.parse::<u32>())
+        .transpose()
+        .map_err(|e| EncItError::InvalidCommand(format!("invalid password-iterations: {}", e)))?;
+    if password_iterations.is_some_and(|iterations| iterations < PBES2_MIN_ITERATIONS) {
+        return Err(EncItError::InvalidCommand(format!(
+            "the password iterations have to be at least {}",
+            PBES2_MIN_ITERATIONS
+        )));
+    }
+    let expires_in = cmd_matches
+        .value_of("expires-in")
+        .map(humantime::parse_duration)
+        .transpose()
+        .map_err(|e| EncItError::InvalidCommand(format!("invalid expires-in: {}", e)))?;
+    let options = EncItEncryptOptions {
+        subject: cmd_matches.value_of("subject").map(|s| s.to_string()),
+        expires_in,
+        burn_after_reading: cmd_matches.is_present("burn-after-reading"),
+        embed_key: cmd_matches.is_present("embed-key"),
+        compression: if cmd_matches.is_present("compress") {
+            EncItCompression::Always
+        } else if cmd_matches.is_present("auto-compress") {
+            EncItCompression::Auto
+        } else {
+            EncItCompression::Never
+        },
+        password: if cmd_matches.is_present("password") {
+            Some(read_new_password(password_reader)?)
+        } else {
+            None
+        },
+        password_iterations,
+        hide_recipients: cmd_matches.is_present("hide-recipient"),
+        file: match cmd_matches.value_of("file") {
+            // a directory is always sent with its name, as an archive
+            Some(file) if cmd_matches.is_present("metadata") || Path::new(file).is_dir() => {
+                Some(read_file_metadata(Path::new(file))?)
+            }
+            _ => None,
+        },
+    };
+    if cmd_matches.is_present("stream") {
+        return encrypt_stream(
+            enc_it.as_ref(),
+            identity,
+            &friends,
+            &options,
+            &mut *reader.borrow_mut(),
+            &mut *writer.borrow_mut(),
+        );
+    }
     let mut message = Vec::new();
 
     reader.borrow_mut().read_to_end(&mut message)?;
 
     debug!("message: {:?}", &message);
-    let b64_message = base64::encode(message);
 
-    let enc_message = enc_it.encrypt(identity, friend, subject, &b64_message)?;
+    let mut enc_message = enc_it.encrypt(identity, &friends, &options, &message)?;
+    if cmd_matches.is_present("armor") {
+        let version = format!("encit {}", env!("CARGO_PKG_VERSION"));
+        let mut headers = vec![(ARMOR_VERSION_HEADER, version.as_str())];
+        if let Some(comment) = cmd_matches.value_of("comment") {
+            headers.push((ARMOR_COMMENT_HEADER, comment));
+        }
+        enc_message = armor(&enc_message, &headers)?;
+    }
+    if cmd_matches.is_present("qr") {
+        enc_message = render_qr_frames(enc_message.as_bytes(), QR_MESSAGE_TITLE)?;
+    }
     writer
         .borrow_mut()
         .write_all(enc_message.as_bytes())
@@ -76,9 +256,11 @@ fn encrypt(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::enc::MockEncIt;
+    use crate::cmd::password::MockEncItPasswordReader;
+    use crate::enc::{EncItPassword, MockEncIt};
     use rand::distributions::Alphanumeric;
     use rand::{thread_rng, Rng};
+    use std::time::Duration;
 
     #[test]
     fn encrypt_stdin() -> Result<(), EncItError> {
@@ -101,27 +283,396 @@ mod tests {
             .map(char::from)
             .collect();
         let message2 = Box::leak(Box::new(message.clone()));
-        let message_bytes = message.as_bytes();
-        let b64_message = base64::encode(message_bytes);
 
         let mut encit_mock = MockEncIt::new();
         encit_mock
             .expect_encrypt()
             .withf(
-                move |identity_name_param, friend_name_param, subject_param, message_param| {
-                    identity_name_param == identity_name
-                        && friend_name_param == friend_name
-                        && *subject_param == Some(subject)
-                        && message_param == b64_message
+                move |identity_name_param, friend_names_param, options_param, message_param| {
+                    *identity_name_param == Some(identity_name)
+                        && friend_names_param == [friend_name]
+                        && options_param.subject.as_deref() == Some(subject)
+                        && options_param.expires_in.is_none()
+                        && message_param == message.as_bytes()
                 },
             )
             .returning(|_, _, _, _| Ok(String::from("fake enc")));
         let rc_encit_mock = Rc::new(encit_mock);
         let in_message = RefCell::new(Box::new(message2.as_bytes()));
         let writer: Rc<RefCell<Vec<u8>>> = Rc::new(RefCell::new(Vec::new()));
-        encrypt(&cmd_matches, rc_encit_mock, in_message, writer.clone())?;
+        encrypt(
+            &cmd_matches,
+            rc_encit_mock,
+            in_message,
+            writer.clone(),
+            &MockEncItPasswordReader::new(),
+        )?;
         let result = String::from_utf8(writer.borrow().to_vec())?;
         assert_eq!(result, "fake enc");
         Ok(())
     }
+
+    #[test]
+    fn encrypt_anonymous() -> Result<(), EncItError> {
+        let cmd = encrypt_cmd();
+        let cmd_matches =
+            cmd.get_matches_from(vec!["encrypt", "--anonymous", "--friend", "friend1"]);
+
+        let mut encit_mock = MockEncIt::new();
+        encit_mock
+            .expect_encrypt()
+            .withf(
+                move |identity_name_param, friend_names_param, options_param, message_param| {
+                    identity_name_param.is_none()
+                        && friend_names_param == ["friend1"]
+                        && *options_param == EncItEncryptOptions::default()
+                        && message_param == b"drop-box"
+                },
+            )
+            .returning(|_, _, _, _| Ok(String::from("fake enc")));
+        let in_message = RefCell::new(Box::new("drop-box".as_bytes()));
+        let writer: Rc<RefCell<Vec<u8>>> = Rc::new(RefCell::new(Vec::new()));
+        encrypt(
+            &cmd_matches,
+            Rc::new(encit_mock),
+            in_message,
+            writer.clone(),
+            &MockEncItPasswordReader::new(),
+        )?;
+        assert_eq!(String::from_utf8(writer.borrow().to_vec())?, "fake enc");
+        Ok(())
+    }
+
+    #[test]
+    fn encrypt_anonymous_with_identity() {
+        let result = encrypt_cmd().get_matches_from_safe(vec![
+            "encrypt",
+            "--anonymous",
+            "--identity",
+            "identity1",
+            "--friend",
+            "friend1",
+        ]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn encrypt_expires_in() -> Result<(), EncItError> {
+        let cmd = encrypt_cmd();
+        let cmd_matches = cmd.get_matches_from(vec![
+            "encrypt",
+            "-i",
+            "identity1",
+            "-f",
+            "friend1",
+            "--expires-in",
+            "7d",
+        ]);
+        let mut encit_mock = MockEncIt::new();
+        encit_mock
+            .expect_encrypt()
+            .withf(|_, _, options_param, _| {
+                options_param.expires_in == Some(Duration::from_secs(7 * 24 * 60 * 60))
+                    && !options_param.burn_after_reading
+            })
+            .returning(|_, _, _, _| Ok(String::from("fake enc")));
+        let in_message = RefCell::new(Box::new("credentials".as_bytes()));
+        let writer: Rc<RefCell<Vec<u8>>> = Rc::new(RefCell::new(Vec::new()));
+        encrypt(
+            &cmd_matches,
+            Rc::new(encit_mock),
+            in_message,
+            writer,
+            &MockEncItPasswordReader::new(),
+        )
+    }
+
+    #[test]
+    fn encrypt_invalid_expires_in() {
+        let cmd = encrypt_cmd();
+        let cmd_matches = cmd.get_matches_from(vec![
+            "encrypt",
+            "-i",
+            "identity1",
+            "-f",
+            "friend1",
+            "--expires-in",
+            "soon",
+        ]);
+        let in_message = RefCell::new(Box::new("credentials".as_bytes()));
+        let writer: Rc<RefCell<Vec<u8>>> = Rc::new(RefCell::new(Vec::new()));
+        let result = encrypt(
+            &cmd_matches,
+            Rc::new(MockEncIt::new()),
+            in_message,
+            writer,
+            &MockEncItPasswordReader::new(),
+        );
+        assert!(matches!(result, Err(EncItError::InvalidCommand(_))));
+    }
+
+    #[test]
+    fn encrypt_compress() -> Result<(), EncItError> {
+        for (flag, compression) in [
+            ("--compress", EncItCompression::Always),
+            ("--auto-compress", EncItCompression::Auto),
+        ] {
+            let cmd_matches = encrypt_cmd().get_matches_from(vec![
+                "encrypt",
+                "-i",
+                "identity1",
+                "-f",
+                "friend1",
+                flag,
+            ]);
+            let mut encit_mock = MockEncIt::new();
+            encit_mock
+                .expect_encrypt()
+                .withf(move |_, _, options_param, _| options_param.compression == compression)
+                .returning(|_, _, _, _| Ok(String::from("fake enc")));
+            let in_message = RefCell::new(Box::new("log line\n".as_bytes()));
+            let writer: Rc<RefCell<Vec<u8>>> = Rc::new(RefCell::new(Vec::new()));
+            encrypt(
+                &cmd_matches,
+                Rc::new(encit_mock),
+                in_message,
+                writer,
+                &MockEncItPasswordReader::new(),
+            )?;
+        }
+        let result = encrypt_cmd().get_matches_from_safe(vec![
+            "encrypt",
+            "-i",
+            "identity1",
+            "-f",
+            "friend1",
+            "--compress",
+            "--stream",
+        ]);
+        assert!(result.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn encrypt_password() -> Result<(), EncItError> {
+        let cmd_matches = encrypt_cmd().get_matches_from(vec![
+            "encrypt",
+            "--password",
+            "--password-iterations",
+            "500000",
+        ]);
+        let mut encit_mock = MockEncIt::new();
+        encit_mock
+            .expect_encrypt()
+            .withf(|identity_param, friends_param, options_param, _| {
+                identity_param.is_none()
+                    && friends_param.is_empty()
+                    && options_param.password == Some(EncItPassword::new("s3cret".to_string()))
+                    && options_param.password_iterations == Some(500_000)
+            })
+            .returning(|_, _, _, _| Ok(String::from("fake enc")));
+        let mut password_reader = MockEncItPasswordReader::new();
+        password_reader
+            .expect_read_password()
+            .times(2)
+            .returning(|_| Ok("s3cret".to_string()));
+        let in_message = RefCell::new(Box::new("no identity yet".as_bytes()));
+        let writer: Rc<RefCell<Vec<u8>>> = Rc::new(RefCell::new(Vec::new()));
+        encrypt(
+            &cmd_matches,
+            Rc::new(encit_mock),
+            in_message,
+            writer,
+            &password_reader,
+        )?;
+
+        let result =
+            encrypt_cmd().get_matches_from_safe(vec!["encrypt", "--password", "-f", "friend1"]);
+        assert!(result.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn encrypt_hide_recipient() -> Result<(), EncItError> {
+        let cmd_matches = encrypt_cmd().get_matches_from(vec![
+            "encrypt",
+            "-i",
+            "identity1",
+            "-f",
+            "friend1",
+            "--hide-recipient",
+        ]);
+        let mut encit_mock = MockEncIt::new();
+        encit_mock
+            .expect_encrypt()
+            .withf(|_, _, options_param, _| options_param.hide_recipients)
+            .returning(|_, _, _, _| Ok(String::from("fake enc")));
+        let in_message = RefCell::new(Box::new("credentials".as_bytes()));
+        let writer: Rc<RefCell<Vec<u8>>> = Rc::new(RefCell::new(Vec::new()));
+        encrypt(
+            &cmd_matches,
+            Rc::new(encit_mock),
+            in_message,
+            writer,
+            &MockEncItPasswordReader::new(),
+        )
+    }
+
+    #[test]
+    fn encrypt_burn_after_reading() -> Result<(), EncItError> {
+        let cmd = encrypt_cmd();
+        let cmd_matches = cmd.get_matches_from(vec![
+            "encrypt",
+            "-i",
+            "identity1",
+            "-f",
+            "friend1",
+            "--burn-after-reading",
+        ]);
+        let mut encit_mock = MockEncIt::new();
+        encit_mock
+            .expect_encrypt()
+            .withf(|_, _, options_param, _| {
+                options_param.burn_after_reading && !options_param.embed_key
+            })
+            .returning(|_, _, _, _| Ok(String::from("fake enc")));
+        let in_message = RefCell::new(Box::new("credentials".as_bytes()));
+        let writer: Rc<RefCell<Vec<u8>>> = Rc::new(RefCell::new(Vec::new()));
+        encrypt(
+            &cmd_matches,
+            Rc::new(encit_mock),
+            in_message,
+            writer,
+            &MockEncItPasswordReader::new(),
+        )
+    }
+
+    #[test]
+    fn encrypt_metadata() -> Result<(), EncItError> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("notes.txt");
+        std::fs::write(&path, b"hello")?;
+        let cmd_matches = encrypt_cmd().get_matches_from(vec![
+            "encrypt",
+            "--identity",
+            "identity1",
+            "--friend",
+            "friend1",
+            "--metadata",
+            path.to_str().unwrap(),
+        ]);
+        let mut encit_mock = MockEncIt::new();
+        encit_mock
+            .expect_encrypt()
+            .withf(|_, _, options_param, _| {
+                options_param.file.as_ref().is_some_and(|file| {
+                    file.name.as_deref() == Some("notes.txt")
+                        && file.content_type.as_deref() == Some("text/plain")
+                        && file.size == Some(5)
+                })
+            })
+            .returning(|_, _, _, _| Ok(String::from("fake enc")));
+        let in_message = RefCell::new(Box::new(&b"hello"[..]));
+        let writer: Rc<RefCell<Vec<u8>>> = Rc::new(RefCell::new(Vec::new()));
+        encrypt(
+            &cmd_matches,
+            Rc::new(encit_mock),
+            in_message,
+            writer,
+            &MockEncItPasswordReader::new(),
+        )
+    }
+
+    #[test]
+    fn encrypt_armor() -> Result<(), EncItError> {
+        let cmd_matches = encrypt_cmd().get_matches_from(vec![
+            "encrypt",
+            "--identity",
+            "identity1",
+            "--friend",
+            "friend1",
+            "--armor",
+            "--comment",
+            "for friend1",
+        ]);
+        let mut encit_mock = MockEncIt::new();
+        encit_mock
+            .expect_encrypt()
+            .returning(|_, _, _, _| Ok("a".repeat(100)));
+        let writer: Rc<RefCell<Vec<u8>>> = Rc::new(RefCell::new(Vec::new()));
+        encrypt(
+            &cmd_matches,
+            Rc::new(encit_mock),
+            RefCell::new(Box::new(&b"hello"[..])),
+            writer.clone(),
+            &MockEncItPasswordReader::new(),
+        )?;
+        let result = String::from_utf8(writer.borrow().to_vec())?;
+        let lines: Vec<&str> = result.lines().collect();
+        assert_eq!(lines[0], "-----BEGIN ENCIT MESSAGE-----");
+        assert!(lines[1].starts_with("Version: encit "));
+        assert_eq!(lines[2], "Comment: for friend1");
+        assert_eq!(lines[3], "");
+        assert_eq!(lines[4], "a".repeat(64));
+        assert_eq!(lines[5], "a".repeat(36));
+        assert_eq!(lines[6], "-----END ENCIT MESSAGE-----");
+        Ok(())
+    }
+
+    #[test]
+    fn encrypt_qr() -> Result<(), EncItError> {
+        let cmd_matches = encrypt_cmd().get_matches_from(vec![
+            "encrypt",
+            "--identity",
+            "identity1",
+            "--friend",
+            "friend1",
+            "--qr",
+        ]);
+        let mut encit_mock = MockEncIt::new();
+        encit_mock
+            .expect_encrypt()
+            .returning(|_, _, _, _| Ok("a".repeat(600)));
+        let writer: Rc<RefCell<Vec<u8>>> = Rc::new(RefCell::new(Vec::new()));
+        encrypt(
+            &cmd_matches,
+            Rc::new(encit_mock),
+            RefCell::new(Box::new(&b"hello"[..])),
+            writer.clone(),
+            &MockEncItPasswordReader::new(),
+        )?;
+        let result = String::from_utf8(writer.borrow().to_vec())?;
+        assert!(result.starts_with("encit message (1/3)\n"));
+        assert!(result.contains("\nencit message (3/3)\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn encrypt_qr_too_large() -> Result<(), EncItError> {
+        let cmd_matches = encrypt_cmd().get_matches_from(vec![
+            "encrypt",
+            "--identity",
+            "identity1",
+            "--friend",
+            "friend1",
+            "--qr",
+        ]);
+        let mut encit_mock = MockEncIt::new();
+        encit_mock
+            .expect_encrypt()
+            .returning(|_, _, _, _| Ok("a".repeat(100_000)));
+        let writer: Rc<RefCell<Vec<u8>>> = Rc::new(RefCell::new(Vec::new()));
+        assert!(matches!(
+            encrypt(
+                &cmd_matches,
+                Rc::new(encit_mock),
+                RefCell::new(Box::new(&b"hello"[..])),
+                writer.clone(),
+                &MockEncItPasswordReader::new(),
+            ),
+            Err(EncItError::InvalidCommand(_))
+        ));
+        assert!(writer.borrow().is_empty());
+        Ok(())
+    }
 }
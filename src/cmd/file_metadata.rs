@@ -0,0 +1,213 @@
+use crate::cmd::archive::ARCHIVE_CONTENT_TYPE;
+use crate::enc::EncItFileMetadata;
+use crate::EncItError;
+use std::fs::{metadata, Permissions};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tempfile::NamedTempFile;
+
+const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
+/// setuid, setgid and sticky bits are never restored
+const RESTORED_MODE_MASK: u32 = 0o777;
+
+const CONTENT_TYPES: [(&str, &str); 22] = [
+    ("txt", "text/plain"),
+    ("md", "text/markdown"),
+    ("csv", "text/csv"),
+    ("html", "text/html"),
+    ("htm", "text/html"),
+    ("xml", "application/xml"),
+    ("json", "application/json"),
+    ("yml", "application/yaml"),
+    ("yaml", "application/yaml"),
+    ("pdf", "application/pdf"),
+    ("zip", "application/zip"),
+    ("gz", "application/gzip"),
+    ("tar", "application/x-tar"),
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("svg", "image/svg+xml"),
+    ("mp3", "audio/mpeg"),
+    ("mp4", "video/mp4"),
+    ("doc", "application/msword"),
+    (
+        "docx",
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+    ),
+];
+
+/// content type guessed from the file extension
+fn content_type(path: &Path) -> &'static str {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_ascii_lowercase())
+        .and_then(|extension| {
+            CONTENT_TYPES
+                .iter()
+                .find(|(known, _)| *known == extension)
+                .map(|(_, content_type)| *content_type)
+        })
+        .unwrap_or(DEFAULT_CONTENT_TYPE)
+}
+
+/// metadata of the file to encrypt, the directories are not part of the name
+pub fn read_file_metadata(path: &Path) -> Result<EncItFileMetadata, EncItError> {
+    let file_metadata = metadata(path)?;
+    // a directory is sent as an archive, its name is known also for "."
+    let (name, content_type, size) = if file_metadata.is_dir() {
+        (
+            path.canonicalize()?.file_name().map(|name| name.to_owned()),
+            ARCHIVE_CONTENT_TYPE,
+            None,
+        )
+    } else {
+        (
+            path.file_name().map(|name| name.to_owned()),
+            content_type(path),
+            Some(file_metadata.len()),
+        )
+    };
+    Ok(EncItFileMetadata {
+        name: name.and_then(|name| name.to_str().map(|name| name.to_string())),
+        content_type: Some(content_type.to_string()),
+        size,
+        mtime: file_metadata
+            .modified()
+            .ok()
+            .and_then(|mtime| mtime.duration_since(UNIX_EPOCH).ok())
+            .map(|mtime| mtime.as_secs()),
+        mode: Some(file_metadata.permissions().mode() & RESTORED_MODE_MASK),
+    })
+}
+
+/// the file name sent in the message has to be a single plain path component
+pub fn safe_file_name(name: &str) -> Result<&str, EncItError> {
+    let mut components = Path::new(name).components();
+    let valid = matches!(components.next(), Some(Component::Normal(_)))
+        && components.next().is_none()
+        && !name.contains(['/', '\\'])
+        && !name.chars().any(char::is_control);
+    if valid {
+        Ok(name)
+    } else {
+        Err(EncItError::InvalidFileName(format!("{:?}", name)))
+    }
+}
+
+/// path of the decrypted file inside the output directory
+pub fn output_file_path(
+    output_dir: &Path,
+    file: Option<&EncItFileMetadata>,
+) -> Result<PathBuf, EncItError> {
+    if file.and_then(|file| file.content_type.as_deref()) == Some(ARCHIVE_CONTENT_TYPE) {
+        return Err(EncItError::InvalidCommand(
+            "the message contains a directory, use --extract".to_string(),
+        ));
+    }
+    let name = file.and_then(|file| file.name.as_deref()).ok_or_else(|| {
+        EncItError::InvalidFileName("the message does not contain a file name".to_string())
+    })?;
+    Ok(output_dir.join(safe_file_name(name)?))
+}
+
+/// restores the mode and the modification time of the decrypted temporary file and moves it
+/// to its final path, an existing file is never overwritten
+pub fn persist_output_file(
+    temp_file: NamedTempFile,
+    path: &Path,
+    file: Option<&EncItFileMetadata>,
+) -> Result<(), EncItError> {
+    if let Some(file) = file {
+        if file.size.is_some_and(|size| {
+            temp_file
+                .as_file()
+                .metadata()
+                .is_ok_and(|written| written.len() != size)
+        }) {
+            eprintln!("Warning: the decrypted file size differs from the sender's file size");
+        }
+        if let Some(mode) = file.mode {
+            temp_file
+                .as_file()
+                .set_permissions(Permissions::from_mode(mode & RESTORED_MODE_MASK))?;
+        }
+        if let Some(mtime) = file.mtime {
+            temp_file
+                .as_file()
+                .set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(mtime))?;
+        }
+    }
+    temp_file
+        .persist_noclobber(path)
+        .map_err(|e| EncItError::IoError(format!("{}: {}", path.display(), e.error)))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::read;
+    use std::io::Write;
+
+    #[test]
+    fn read_metadata() -> Result<(), EncItError> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("report.PDF");
+        std::fs::write(&path, b"pdf")?;
+        std::fs::set_permissions(&path, Permissions::from_mode(0o640))?;
+        let file = read_file_metadata(&path)?;
+        assert_eq!(file.name.as_deref(), Some("report.PDF"));
+        assert_eq!(file.content_type.as_deref(), Some("application/pdf"));
+        assert_eq!(file.size, Some(3));
+        assert_eq!(file.mode, Some(0o640));
+        assert!(file.mtime.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn reject_unsafe_names() {
+        for name in [
+            "",
+            ".",
+            "..",
+            "../passwd",
+            "/etc/passwd",
+            "a/b",
+            "a\\b",
+            "a\nb",
+        ] {
+            assert!(safe_file_name(name).is_err(), "{:?} accepted", name);
+        }
+        assert_eq!(safe_file_name(".hidden").ok(), Some(".hidden"));
+        assert!(output_file_path(Path::new("out"), None).is_err());
+    }
+
+    #[test]
+    fn restore_file() -> Result<(), EncItError> {
+        let dir = tempfile::tempdir()?;
+        let file = EncItFileMetadata {
+            name: Some("notes.txt".to_string()),
+            content_type: None,
+            size: Some(5),
+            mtime: Some(1_600_000_000),
+            mode: Some(0o4600),
+        };
+        let path = output_file_path(dir.path(), Some(&file))?;
+        let mut temp_file = NamedTempFile::new_in(dir.path())?;
+        temp_file.write_all(b"hello")?;
+        persist_output_file(temp_file, &path, Some(&file))?;
+        assert_eq!(read(&path)?, b"hello");
+        let restored = read_file_metadata(&path)?;
+        assert_eq!(restored.mode, Some(0o600));
+        assert_eq!(restored.mtime, Some(1_600_000_000));
+
+        // an existing file is not overwritten
+        let temp_file = NamedTempFile::new_in(dir.path())?;
+        assert!(persist_output_file(temp_file, &path, Some(&file)).is_err());
+        assert_eq!(read(&path)?, b"hello");
+        Ok(())
+    }
+}
@@ -0,0 +1,106 @@
+use crate::fingerprint::{grouped_fingerprint, numeric_fingerprint};
+use crate::{EncItConfig, EncItError};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use std::cell::RefCell;
+use std::io::{stdout, Write};
+use std::rc::Rc;
+
+pub fn fingerprint_cmd<'a>() -> App<'a, 'a> {
+    SubCommand::with_name("fingerprint")
+        .about("display the key fingerprint of a friend or identity")
+        .arg(
+            Arg::with_name("name")
+                .takes_value(true)
+                .required(true)
+                .help("Friend or identity name"),
+        )
+}
+
+pub fn fingerprint_exec(
+    cmd_matches: &ArgMatches,
+    config: Rc<dyn EncItConfig>,
+) -> Result<(), EncItError> {
+    fingerprint(cmd_matches, config, Rc::new(RefCell::new(stdout())))
+}
+
+fn fingerprint(
+    cmd_matches: &ArgMatches,
+    config: Rc<dyn EncItConfig>,
+    writer: Rc<RefCell<dyn Write>>,
+) -> Result<(), EncItError> {
+    let name = cmd_matches.value_of("name").unwrap();
+    // an identity and a friend can have the same name, both are displayed
+    let mut fingerprints = Vec::with_capacity(2);
+    if let Some(identity) = config.identity(name) {
+        fingerprints.push(("identity", identity.private_key().public_key_fingerprint()?));
+    }
+    if let Some(friend) = config.friend(name) {
+        fingerprints.push(("friend", friend.public_key().fingerprint()?));
+    }
+    if fingerprints.is_empty() {
+        return Err(EncItError::FriendNotFound(name.to_string()));
+    }
+    let mut writer = writer.borrow_mut();
+    for (kind, fingerprint) in fingerprints {
+        writeln!(writer, "{} {}", kind, name)?;
+        writeln!(writer, "fingerprint: {}", grouped_fingerprint(&fingerprint))?;
+        writeln!(writer, "numeric:     {}", numeric_fingerprint(&fingerprint))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{EncItFriend, EncItIdentity, MockEncItConfig};
+    use crate::enc::tests::{generate_friend, generate_identity};
+    use crate::fingerprint::public_key_fingerprint;
+
+    #[test]
+    fn friend_fingerprint() -> Result<(), EncItError> {
+        let cmd_matches = fingerprint_cmd().get_matches_from(vec!["fingerprint", "bob"]);
+        let (bob_private_key, bob) = generate_friend("bob", None);
+        let bob: &'static EncItFriend = Box::leak(bob);
+        let mut cfg = MockEncItConfig::new();
+        cfg.expect_identity().returning(|_| None);
+        cfg.expect_friend()
+            .returning(move |name| Some(bob).filter(|bob| bob.name() == name));
+        let writer: Rc<RefCell<Vec<u8>>> = Rc::new(RefCell::new(Vec::new()));
+        fingerprint(&cmd_matches, Rc::new(cfg), writer.clone())?;
+
+        let expected = public_key_fingerprint(&bob_private_key)?;
+        let result = String::from_utf8(writer.borrow().to_vec())?;
+        assert_eq!(
+            result,
+            format!(
+                "friend bob\nfingerprint: {}\nnumeric:     {}\n",
+                grouped_fingerprint(&expected),
+                numeric_fingerprint(&expected)
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn identity_fingerprint() -> Result<(), EncItError> {
+        let cmd_matches = fingerprint_cmd().get_matches_from(vec!["fingerprint", "myself"]);
+        let (_, myself) = generate_identity("myself", None);
+        let myself: &'static EncItIdentity = Box::leak(myself);
+        let mut cfg = MockEncItConfig::new();
+        cfg.expect_identity().returning(move |_| Some(myself));
+        cfg.expect_friend().returning(|_| None);
+        let writer: Rc<RefCell<Vec<u8>>> = Rc::new(RefCell::new(Vec::new()));
+        fingerprint(&cmd_matches, Rc::new(cfg), writer.clone())?;
+        let result = String::from_utf8(writer.borrow().to_vec())?;
+        assert!(result.starts_with("identity myself\nfingerprint: "));
+
+        let mut cfg = MockEncItConfig::new();
+        cfg.expect_identity().returning(|_| None);
+        cfg.expect_friend().returning(|_| None);
+        assert!(matches!(
+            fingerprint(&cmd_matches, Rc::new(cfg), writer),
+            Err(EncItError::FriendNotFound(_))
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,162 @@
+use crate::armor::{armor, dearmor, ARMOR_VERSION_HEADER};
+use crate::cmd::password::{
+    confirm_unverified_friends, EncItPasswordReader, TtyConfirmReader, TtyPasswordReader,
+};
+use crate::cmd::reader::{get_file_reader, EncItFileReader};
+use crate::enc::{is_password_encrypted, EncIt, EncItDecryptOptions, EncItImpl, EncItPassword};
+use crate::{EncItConfig, EncItError};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use std::cell::RefCell;
+use std::io::{stdout, Read, Write};
+use std::rc::Rc;
+
+pub fn forward_cmd<'a>() -> App<'a, 'a> {
+    SubCommand::with_name("forward")
+        .about("forward an encIt message keeping the original sender signature")
+        .arg(
+            Arg::with_name("identity")
+                .long("identity")
+                .short("i")
+                .required(true)
+                .takes_value(true)
+                .help("Identity name (has to be present in the encit configuration file)"),
+        )
+        .arg(
+            Arg::with_name("friend")
+                .long("friend")
+                .short("f")
+                .required(true)
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help(
+                    "Friend name (has to be present in the encit configuration file), repeatable",
+                ),
+        )
+        .arg(
+            Arg::with_name("allow-unknown-sender")
+                .long("allow-unknown-sender")
+                .help("Forward messages from senders not in the friends list (not verified)"),
+        )
+        .arg(
+            Arg::with_name("confirm-unverified")
+                .long("confirm-unverified")
+                .help("Ask for a confirmation before forwarding to a friend that is not verified"),
+        )
+        .arg(
+            Arg::with_name("armor")
+                .long("armor")
+                .help("Wrap the message in text lines between BEGIN/END ENCIT MESSAGE markers"),
+        )
+        .arg(
+            Arg::with_name("file")
+                .takes_value(true)
+                .help("file to forward"),
+        )
+}
+
+pub fn forward_exec(
+    cmd_matches: &ArgMatches,
+    config: Rc<dyn EncItConfig>,
+) -> Result<(), EncItError> {
+    if cmd_matches.is_present("confirm-unverified") {
+        let friends: Vec<&str> = cmd_matches
+            .values_of("friend")
+            .into_iter()
+            .flatten()
+            .collect();
+        confirm_unverified_friends(&friends, config.as_ref(), &TtyConfirmReader)?;
+    }
+    let enc_it = Rc::new(EncItImpl::new(config));
+    let reader = RefCell::new(get_file_reader(cmd_matches, "file")?);
+    let writer = Rc::new(RefCell::new(stdout()));
+    forward(cmd_matches, enc_it, reader, writer, &TtyPasswordReader)
+}
+
+fn forward(
+    cmd_matches: &ArgMatches,
+    enc_it: Rc<dyn EncIt>,
+    reader: RefCell<Box<dyn EncItFileReader>>,
+    writer: Rc<RefCell<dyn Write>>,
+    password_reader: &dyn EncItPasswordReader,
+) -> Result<(), EncItError> {
+    let identity = cmd_matches.value_of("identity").unwrap();
+    let friends: Vec<&str> = cmd_matches
+        .values_of("friend")
+        .map(|friends| friends.collect())
+        .unwrap_or_default();
+    let mut encrypted_message = String::new();
+    reader.borrow_mut().read_to_string(&mut encrypted_message)?;
+    let encrypted_message = dearmor(&encrypted_message)?;
+    let options = EncItDecryptOptions {
+        allow_expired: false,
+        allow_unknown_sender: cmd_matches.is_present("allow-unknown-sender"),
+        password: if is_password_encrypted(&encrypted_message) {
+            Some(EncItPassword::new(
+                password_reader.read_password("Password: ")?,
+            ))
+        } else {
+            None
+        },
+        defer_seen: false,
+    };
+    let mut forwarded_message = enc_it.forward(&encrypted_message, identity, &friends, &options)?;
+    if cmd_matches.is_present("armor") {
+        let version = format!("encit {}", env!("CARGO_PKG_VERSION"));
+        forwarded_message = armor(&forwarded_message, &[(ARMOR_VERSION_HEADER, &version)])?;
+    }
+    writer
+        .borrow_mut()
+        .write_all(forwarded_message.as_bytes())
+        .map_err(|e| e.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::armor::ARMOR_BEGIN;
+    use crate::cmd::password::MockEncItPasswordReader;
+    use crate::enc::MockEncIt;
+
+    #[test]
+    fn forward_test() -> Result<(), EncItError> {
+        let cmd_matches = forward_cmd().get_matches_from(vec![
+            "forward",
+            "-i",
+            "identity-1",
+            "-f",
+            "friend-1",
+            "-f",
+            "friend-2",
+            "--armor",
+        ]);
+        let mut encit_mock = MockEncIt::new();
+        encit_mock
+            .expect_forward()
+            .withf(|jwe_param, identity_param, friends_param, options_param| {
+                jwe_param == "header.key.iv.ciphertext.tag"
+                    && identity_param == "identity-1"
+                    && friends_param == ["friend-1", "friend-2"]
+                    && options_param.password.is_none()
+                    && !options_param.allow_unknown_sender
+            })
+            .returning(|_, _, _, _| Ok("forwarded".to_string()));
+        let armored = armor("header.key.iv.ciphertext.tag", &[])?;
+        let armored = Box::leak(Box::new(armored));
+        let reader = RefCell::new(Box::new(armored.as_bytes()));
+
+        let writer: Rc<RefCell<Vec<u8>>> = Rc::new(RefCell::new(Vec::new()));
+        forward(
+            &cmd_matches,
+            Rc::new(encit_mock),
+            reader,
+            writer.clone(),
+            &MockEncItPasswordReader::new(),
+        )?;
+
+        let result = String::from_utf8(writer.borrow().to_vec())?;
+        assert!(result.starts_with(ARMOR_BEGIN));
+        assert_eq!(dearmor(&result)?, "forwarded");
+        Ok(())
+    }
+}
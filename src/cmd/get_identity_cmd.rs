@@ -1,3 +1,4 @@
+use crate::qr::render_qr_frames;
 use crate::{EncItConfig, EncItError};
 use clap::{App, Arg, ArgMatches, SubCommand};
 use std::cell::RefCell;
@@ -21,6 +22,12 @@ pub fn get_identity_cmd<'a>() -> App<'a, 'a> {
                 .long("private-key")
                 .help("display private key"),
         )
+        .arg(
+            Arg::with_name("qr")
+                .long("qr")
+                .conflicts_with("private-key")
+                .help("display the public key (base64-pem) as QR code"),
+        )
 }
 
 pub fn get_identity_exec(
@@ -41,7 +48,17 @@ fn get_identity(
         return Err(EncItError::IdentityNotFound(identity_name.to_string()));
     }
     let identity = identity.unwrap();
-    if arg_matches.is_present("private-key") {
+    if arg_matches.is_present("qr") {
+        // the base64 PEM is the most compact format accepted by `add friend`
+        let public_key = base64::encode(identity.private_key().public_key_pem()?);
+        let title = format!(
+            "{} {}",
+            identity.name(),
+            identity.private_key().public_key_thumbprint()?
+        );
+        let qr_code = render_qr_frames(public_key.as_bytes(), &title)?;
+        writer.borrow_mut().write_all(qr_code.as_bytes())?;
+    } else if arg_matches.is_present("private-key") {
         let private_key = match arg_matches.value_of("format").unwrap() {
             "pem" => identity
                 .private_key()
@@ -71,7 +88,8 @@ fn get_identity(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::{EncItIdentity, EncItPrivateKey, MockEncItConfig};
+    use crate::config::{EncItIdentity, EncItKeyType, EncItPrivateKey, MockEncItConfig};
+    use crate::enc::tests::generate_identity;
     use crate::{EncItConfig, EncItError, EncItPEM};
     use mockall::predicate::eq;
     use std::cell::RefCell;
@@ -94,6 +112,7 @@ mod tests {
         let expected_output = hex::encode(
             expected_pem
                 .private_key(Some("test"))?
+                .rsa()?
                 .private_key_to_pem()?,
         );
         get_identity_test("hex-pem", true, expected_output)
@@ -116,6 +135,7 @@ mod tests {
         let expected_output = base64::encode(
             expected_pem
                 .private_key(Some("test"))?
+                .rsa()?
                 .private_key_to_pem()?,
         );
         get_identity_test("base64-pem", true, expected_output)
@@ -138,6 +158,7 @@ mod tests {
         let expected_output = String::from_utf8(
             expected_pem
                 .private_key(Some("test"))?
+                .rsa()?
                 .private_key_to_pem()?,
         )?;
         get_identity_test("pem", true, expected_output)
@@ -180,4 +201,25 @@ mod tests {
         assert_eq!(result, expected_output);
         Ok(())
     }
+
+    #[test]
+    fn get_identity_qr() -> Result<(), EncItError> {
+        let cmd_matches = get_identity_cmd().get_matches_from(vec!["identity", "myself", "--qr"]);
+        let (_, identity) = generate_identity("myself", Some(EncItKeyType::Ed25519.generate()?));
+        let identity: &'static EncItIdentity = Box::leak(identity);
+        let mut cfg = MockEncItConfig::new();
+        cfg.expect_identity()
+            .with(eq("myself"))
+            .returning(move |_| Some(identity));
+        let writer: Rc<RefCell<Vec<u8>>> = Rc::new(RefCell::new(Vec::new()));
+        get_identity(&cmd_matches, Rc::new(cfg), writer.clone())?;
+        let result = String::from_utf8(writer.borrow().to_vec())?;
+        let title = format!(
+            "myself {}\n",
+            identity.private_key().public_key_thumbprint()?
+        );
+        assert!(result.starts_with(&title));
+        assert!(result.contains('▀'));
+        Ok(())
+    }
 }
@@ -1,11 +1,21 @@
 mod add_cmd;
 mod add_friend_cmd;
 mod add_identity_cmd;
+mod archive;
 mod decrypt_cmd;
 mod encrypt_cmd;
+mod file_metadata;
+mod fingerprint_cmd;
+mod forward_cmd;
 mod get_friends_cmd;
 mod get_identities_cmd;
 mod get_identity_cmd;
 mod new_identity_cmd;
+mod password;
 mod reader;
 pub mod root_cmd;
+mod rotate_identity_cmd;
+mod safety_number_cmd;
+mod sign_cmd;
+mod verify_cmd;
+mod verify_friend_cmd;
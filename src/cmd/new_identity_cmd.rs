@@ -1,10 +1,20 @@
+use crate::config::EncItKeyType;
 use crate::{EncItConfig, EncItError, EncItPEM};
 use clap::{App, Arg, ArgMatches, SubCommand};
-use openssl::rsa::Rsa;
 use std::rc::Rc;
 
 pub fn new_identity_cmd<'a>() -> App<'a, 'a> {
-    SubCommand::with_name("identity").arg(Arg::with_name("name").takes_value(true).required(true))
+    SubCommand::with_name("identity")
+        .arg(Arg::with_name("name").takes_value(true).required(true))
+        .arg(
+            Arg::with_name("type")
+                .long("type")
+                .short("t")
+                .takes_value(true)
+                .default_value("rsa")
+                .possible_values(&EncItKeyType::NAMES)
+                .help("Identity key type"),
+        )
 }
 
 pub fn new_identity_exec(
@@ -12,15 +22,16 @@ pub fn new_identity_exec(
     config: Rc<dyn EncItConfig>,
 ) -> Result<(), EncItError> {
     let identity_name = arg_matches.value_of("name").unwrap();
-    let key = Rsa::generate(2048)?;
-    let key = EncItPEM::Hex(hex::encode(key.private_key_to_pem()?));
+    let key_type = EncItKeyType::from_name(arg_matches.value_of("type").unwrap())?;
+    let key = key_type.generate()?;
+    let key = EncItPEM::Hex(hex::encode(EncItKeyType::private_key_to_pem(&key)?));
     config.add_identity(identity_name, &key, None)?.save()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::MockEncItConfig;
+    use crate::config::{EncItPrivateKey, MockEncItConfig};
     use crate::EncItError;
 
     #[test]
@@ -43,4 +54,29 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn new_ec_identity_test() -> Result<(), EncItError> {
+        let cmd = new_identity_cmd();
+        let cmd_matches =
+            cmd.get_matches_from(vec!["identity", "ec-identity", "--type", "ec-p256"]);
+        let mut cfg_mock = MockEncItConfig::new();
+        cfg_mock
+            .expect_add_identity()
+            .withf(|identity_name_param, private_key, _| {
+                identity_name_param == "ec-identity"
+                    && EncItPrivateKey::new(private_key.clone(), None)
+                        .key_type()
+                        .ok()
+                        == Some(EncItKeyType::EcP256)
+            })
+            .returning(|_, _, _| {
+                let mut new_cfg = MockEncItConfig::new();
+                new_cfg.expect_save().returning(|| Ok(()));
+                Ok(Box::new(new_cfg))
+            });
+        new_identity_exec(&cmd_matches, Rc::new(cfg_mock))?;
+
+        Ok(())
+    }
 }
@@ -0,0 +1,174 @@
+use crate::config::EncItTrustLevel;
+use crate::enc::EncItPassword;
+use crate::{EncItConfig, EncItError};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::process::Command;
+
+#[cfg(test)]
+use mockall::automock;
+
+#[cfg_attr(test, automock)]
+pub trait EncItPasswordReader {
+    fn read_password(&self, prompt: &str) -> Result<String, EncItError>;
+}
+
+/// prompts on the terminal, so that the message can still be piped through stdin
+pub struct TtyPasswordReader;
+
+impl EncItPasswordReader for TtyPasswordReader {
+    fn read_password(&self, prompt: &str) -> Result<String, EncItError> {
+        let mut tty = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+        write!(tty, "{}", prompt)?;
+        tty.flush()?;
+        set_echo(&tty, false)?;
+        let mut password = String::new();
+        let read_result = BufReader::new(&tty).read_line(&mut password);
+        set_echo(&tty, true)?;
+        writeln!(tty)?;
+        read_result?;
+        Ok(password.trim_end_matches(&['\r', '\n'][..]).to_string())
+    }
+}
+
+#[cfg_attr(test, automock)]
+pub trait EncItConfirmReader {
+    fn confirm(&self, prompt: &str) -> Result<bool, EncItError>;
+}
+
+/// asks a yes/no question on the terminal, anything but y/yes is a no
+pub struct TtyConfirmReader;
+
+impl EncItConfirmReader for TtyConfirmReader {
+    fn confirm(&self, prompt: &str) -> Result<bool, EncItError> {
+        let mut tty = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+        write!(tty, "{} [y/N] ", prompt)?;
+        tty.flush()?;
+        let mut answer = String::new();
+        BufReader::new(&tty).read_line(&mut answer)?;
+        Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+    }
+}
+
+/// the unknown and revoked friends are left to the encryption, which refuses them
+pub fn confirm_unverified_friends(
+    friends: &[&str],
+    config: &dyn EncItConfig,
+    confirm_reader: &dyn EncItConfirmReader,
+) -> Result<(), EncItError> {
+    for friend in friends.iter().filter_map(|name| config.friend(name)) {
+        if !matches!(
+            friend.trust(),
+            EncItTrustLevel::Unverified | EncItTrustLevel::Tofu
+        ) {
+            continue;
+        }
+        let prompt = format!(
+            "The key of {} is {}, encrypt anyway?",
+            friend.name(),
+            friend.trust()
+        );
+        if !confirm_reader.confirm(&prompt)? {
+            return Err(EncItError::InvalidCommand(format!(
+                "encryption for the unverified friend {} cancelled",
+                friend.name()
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// the password is never read while the terminal echoes it
+fn set_echo(tty: &File, echo: bool) -> Result<(), EncItError> {
+    let status = Command::new("stty")
+        .arg(if echo { "echo" } else { "-echo" })
+        .stdin(tty.try_clone()?)
+        .status()?;
+    if !status.success() {
+        return Err(EncItError::IoError(format!(
+            "cannot turn the terminal echo {} ({})",
+            if echo { "on" } else { "off" },
+            status
+        )));
+    }
+    Ok(())
+}
+
+/// the password used to encrypt has to be typed twice
+pub fn read_new_password(
+    password_reader: &dyn EncItPasswordReader,
+) -> Result<EncItPassword, EncItError> {
+    let password = password_reader.read_password("Password: ")?;
+    if password.is_empty() {
+        return Err(EncItError::InvalidCommand("empty password".to_string()));
+    }
+    if password_reader.read_password("Confirm password: ")? != password {
+        return Err(EncItError::InvalidCommand(
+            "the passwords do not match".to_string(),
+        ));
+    }
+    Ok(EncItPassword::new(password))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{EncItFriend, MockEncItConfig};
+    use crate::enc::tests::generate_friend;
+    use mockall::predicate::eq;
+    use std::sync::{Arc, Mutex};
+
+    fn password_reader_mock(passwords: &[&'static str]) -> MockEncItPasswordReader {
+        let passwords = Arc::new(Mutex::new(passwords.to_vec()));
+        let mut password_reader = MockEncItPasswordReader::new();
+        password_reader
+            .expect_read_password()
+            .returning(move |_| Ok(passwords.lock().unwrap().remove(0).to_string()));
+        password_reader
+    }
+
+    #[test]
+    fn read_new_password_test() -> Result<(), EncItError> {
+        let password = read_new_password(&password_reader_mock(&["s3cret", "s3cret"]))?;
+        assert_eq!(password, EncItPassword::new("s3cret".to_string()));
+        assert!(matches!(
+            read_new_password(&password_reader_mock(&["s3cret", "secret"])),
+            Err(EncItError::InvalidCommand(_))
+        ));
+        assert!(matches!(
+            read_new_password(&password_reader_mock(&[""])),
+            Err(EncItError::InvalidCommand(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn confirm_unverified() -> Result<(), EncItError> {
+        let (_, bob) = generate_friend("bob", None);
+        let bob: &'static EncItFriend = Box::leak(bob);
+        let (_, carol) = generate_friend("carol", None);
+        let carol: &'static EncItFriend = Box::leak(carol);
+        let mut config = MockEncItConfig::new();
+        config.expect_friend().returning(move |name| match name {
+            "bob" => Some(bob),
+            "carol" => Some(carol),
+            _ => None,
+        });
+        let mut confirm_reader = MockEncItConfirmReader::new();
+        confirm_reader
+            .expect_confirm()
+            .with(eq("The key of bob is unverified, encrypt anyway?"))
+            .times(1)
+            .returning(|_| Ok(true));
+        confirm_reader
+            .expect_confirm()
+            .with(eq("The key of carol is unverified, encrypt anyway?"))
+            .times(1)
+            .returning(|_| Ok(false));
+        assert!(matches!(
+            confirm_unverified_friends(&["bob", "carol"], &config, &confirm_reader),
+            Err(EncItError::InvalidCommand(_))
+        ));
+        Ok(())
+    }
+}
@@ -1,10 +1,13 @@
+use crate::cmd::archive::EncItArchiveReader;
 use crate::EncItError;
 use clap::ArgMatches;
 use std::any::Any;
 use std::fs::File;
 use std::io::{stdin, Read, Stdin};
+use std::path::Path;
 
 pub trait EncItFileReader: Read {
+    #[allow(dead_code)]
     fn as_any(&self) -> &dyn Any;
 }
 
@@ -32,3 +35,41 @@ pub fn get_file_reader(
         Ok(stdin)
     }
 }
+
+/// a directory is read as an archive of its files
+pub fn get_file_or_dir_reader(
+    arg_matches: &ArgMatches,
+    param_name: &str,
+) -> Result<Box<dyn EncItFileReader>, EncItError> {
+    match arg_matches.value_of(param_name) {
+        Some(dir_path) if Path::new(dir_path).is_dir() => {
+            Ok(Box::new(EncItArchiveReader::new(Path::new(dir_path))?))
+        }
+        _ => get_file_reader(arg_matches, param_name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{App, Arg};
+
+    #[test]
+    fn get_dir_reader() -> Result<(), EncItError> {
+        let dir = tempfile::tempdir()?;
+        let matches = App::new("test")
+            .arg(Arg::with_name("file").takes_value(true))
+            .get_matches_from(vec!["test", dir.path().to_str().unwrap()]);
+        let reader = get_file_or_dir_reader(&matches, "file")?;
+        assert!(reader
+            .as_any()
+            .downcast_ref::<EncItArchiveReader>()
+            .is_some());
+        // only the commands that accept a directory archive it
+        let mut content = Vec::new();
+        assert!(get_file_reader(&matches, "file")?
+            .read_to_end(&mut content)
+            .is_err());
+        Ok(())
+    }
+}
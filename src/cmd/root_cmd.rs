@@ -1,10 +1,16 @@
-use crate::cmd::add_cmd::add_cmd;
-use crate::cmd::add_friend_cmd::add_friend_exec;
+use crate::cmd::add_friend_cmd::{add_friend_cmd, add_friend_exec};
 use crate::cmd::add_identity_cmd::{add_identity_cmd, add_identity_exec};
 use crate::cmd::decrypt_cmd::{decrypt_cmd, decrypt_exec};
 use crate::cmd::encrypt_cmd::{encrypt_cmd, encrypt_exec};
+use crate::cmd::fingerprint_cmd::{fingerprint_cmd, fingerprint_exec};
+use crate::cmd::forward_cmd::{forward_cmd, forward_exec};
 use crate::cmd::get_identity_cmd::{get_identity_cmd, get_identity_exec};
 use crate::cmd::new_identity_cmd::{new_identity_cmd, new_identity_exec};
+use crate::cmd::rotate_identity_cmd::{rotate_identity_cmd, rotate_identity_exec};
+use crate::cmd::safety_number_cmd::{safety_number_cmd, safety_number_exec};
+use crate::cmd::sign_cmd::{sign_cmd, sign_exec};
+use crate::cmd::verify_cmd::{verify_cmd, verify_exec};
+use crate::cmd::verify_friend_cmd::verify_friend_exec;
 use crate::{EncItConfig, EncItError};
 use clap::{App, Arg, ArgMatches, SubCommand};
 use std::rc::Rc;
@@ -33,8 +39,15 @@ pub trait Commands {
     fn get_identities<'a>(&self, arg_matches: &'a ArgMatches<'a>) -> Result<(), EncItError>;
     fn get_friends<'a>(&self, arg_matches: &'a ArgMatches<'a>) -> Result<(), EncItError>;
     fn new_identity<'a>(&self, arg_matches: &'a ArgMatches<'a>) -> Result<(), EncItError>;
+    fn rotate_identity<'a>(&self, arg_matches: &'a ArgMatches<'a>) -> Result<(), EncItError>;
     fn encrypt<'a>(&self, arg_matches: &'a ArgMatches<'a>) -> Result<(), EncItError>;
     fn decrypt<'a>(&self, arg_matches: &'a ArgMatches<'a>) -> Result<(), EncItError>;
+    fn forward<'a>(&self, arg_matches: &'a ArgMatches<'a>) -> Result<(), EncItError>;
+    fn sign<'a>(&self, arg_matches: &'a ArgMatches<'a>) -> Result<(), EncItError>;
+    fn verify<'a>(&self, arg_matches: &'a ArgMatches<'a>) -> Result<(), EncItError>;
+    fn verify_friend<'a>(&self, arg_matches: &'a ArgMatches<'a>) -> Result<(), EncItError>;
+    fn fingerprint<'a>(&self, arg_matches: &'a ArgMatches<'a>) -> Result<(), EncItError>;
+    fn safety_number<'a>(&self, arg_matches: &'a ArgMatches<'a>) -> Result<(), EncItError>;
 }
 
 impl Commands for CommandsImpl {
@@ -66,6 +79,10 @@ impl Commands for CommandsImpl {
         new_identity_exec(arg_matches, self.get_config())
     }
 
+    fn rotate_identity<'a>(&self, arg_matches: &'a ArgMatches<'a>) -> Result<(), EncItError> {
+        rotate_identity_exec(arg_matches, self.get_config())
+    }
+
     fn encrypt<'a>(&self, arg_matches: &'a ArgMatches<'a>) -> Result<(), EncItError> {
         encrypt_exec(arg_matches, self.get_config())
     }
@@ -73,6 +90,30 @@ impl Commands for CommandsImpl {
     fn decrypt<'a>(&self, arg_matches: &'a ArgMatches<'a>) -> Result<(), EncItError> {
         decrypt_exec(arg_matches, self.get_config())
     }
+
+    fn forward<'a>(&self, arg_matches: &'a ArgMatches<'a>) -> Result<(), EncItError> {
+        forward_exec(arg_matches, self.get_config())
+    }
+
+    fn sign<'a>(&self, arg_matches: &'a ArgMatches<'a>) -> Result<(), EncItError> {
+        sign_exec(arg_matches, self.get_config())
+    }
+
+    fn verify<'a>(&self, arg_matches: &'a ArgMatches<'a>) -> Result<(), EncItError> {
+        verify_exec(arg_matches, self.get_config())
+    }
+
+    fn verify_friend<'a>(&self, arg_matches: &'a ArgMatches<'a>) -> Result<(), EncItError> {
+        verify_friend_exec(arg_matches, self.get_config())
+    }
+
+    fn fingerprint<'a>(&self, arg_matches: &'a ArgMatches<'a>) -> Result<(), EncItError> {
+        fingerprint_exec(arg_matches, self.get_config())
+    }
+
+    fn safety_number<'a>(&self, arg_matches: &'a ArgMatches<'a>) -> Result<(), EncItError> {
+        safety_number_exec(arg_matches, self.get_config())
+    }
 }
 
 pub fn root_cmd<'a>() -> App<'a, 'a> {
@@ -87,6 +128,11 @@ pub fn root_cmd<'a>() -> App<'a, 'a> {
         )
         .subcommand(encrypt_cmd())
         .subcommand(decrypt_cmd())
+        .subcommand(forward_cmd())
+        .subcommand(sign_cmd())
+        .subcommand(verify_cmd())
+        .subcommand(fingerprint_cmd())
+        .subcommand(safety_number_cmd())
         .subcommand(
             SubCommand::with_name("get")
                 .about("retrieve encit information")
@@ -97,7 +143,7 @@ pub fn root_cmd<'a>() -> App<'a, 'a> {
         .subcommand(
             SubCommand::with_name("add")
                 .about("add friend/identity to encit")
-                .subcommand(add_cmd("friend"))
+                .subcommand(add_friend_cmd())
                 .subcommand(add_identity_cmd()),
         )
         .subcommand(
@@ -105,6 +151,11 @@ pub fn root_cmd<'a>() -> App<'a, 'a> {
                 .about("create new identity to encIt")
                 .subcommand(new_identity_cmd()),
         )
+        .subcommand(
+            SubCommand::with_name("rotate")
+                .about("replace the key of an identity")
+                .subcommand(rotate_identity_cmd()),
+        )
 }
 
 pub fn root_exec(commands: Rc<dyn Commands>, matches: &ArgMatches) -> Result<(), EncItError> {
@@ -124,8 +175,20 @@ pub fn root_exec(commands: Rc<dyn Commands>, matches: &ArgMatches) -> Result<(),
             ("identity", Some(cmd_matches)) => commands.new_identity(cmd_matches),
             (_, _) => Err(EncItError::InvalidCommand(String::new())),
         },
+        ("rotate", Some(rotate_matches)) => match rotate_matches.subcommand() {
+            ("identity", Some(cmd_matches)) => commands.rotate_identity(cmd_matches),
+            (_, _) => Err(EncItError::InvalidCommand(String::new())),
+        },
         ("encrypt", Some(encrypt_matches)) => commands.encrypt(encrypt_matches),
         ("decrypt", Some(encrypt_matches)) => commands.decrypt(encrypt_matches),
+        ("forward", Some(forward_matches)) => commands.forward(forward_matches),
+        ("sign", Some(sign_matches)) => commands.sign(sign_matches),
+        ("verify", Some(verify_matches)) => match verify_matches.subcommand() {
+            ("friend", Some(cmd_matches)) => commands.verify_friend(cmd_matches),
+            (_, _) => commands.verify(verify_matches),
+        },
+        ("fingerprint", Some(cmd_matches)) => commands.fingerprint(cmd_matches),
+        ("safety-number", Some(cmd_matches)) => commands.safety_number(cmd_matches),
         (_, _) => Err(EncItError::InvalidCommand(String::new())),
     }
 }
@@ -266,6 +329,26 @@ mod tests {
         root_exec(rc_commands, &arg_matches)
     }
 
+    #[test]
+    fn rotate_identity() -> Result<(), EncItError> {
+        let cmd = root_cmd();
+        let arg_matches =
+            Rc::new(cmd.get_matches_from(vec!["encit", "rotate", "identity", "identity1"]));
+        let expected_arg_matches = format!(
+            "{:?}",
+            arg_matches.subcommand().1.unwrap().subcommand().1.unwrap()
+        );
+        let mut commands = MockCommands::new();
+        commands
+            .expect_rotate_identity()
+            .withf(move |arg_matches_param| {
+                expected_arg_matches == format!("{:?}", arg_matches_param)
+            })
+            .returning(|_| Ok(()));
+        let rc_commands: Rc<dyn Commands> = Rc::new(commands);
+        root_exec(rc_commands, &arg_matches)
+    }
+
     #[test]
     fn encrypt() -> Result<(), EncItError> {
         let cmd = root_cmd();
@@ -305,4 +388,153 @@ mod tests {
         let rc_commands: Rc<dyn Commands> = Rc::new(commands);
         root_exec(rc_commands, &arg_matches)
     }
+
+    #[test]
+    fn forward() -> Result<(), EncItError> {
+        let cmd = root_cmd();
+        let arg_matches = Rc::new(cmd.get_matches_from(vec![
+            "encit",
+            "forward",
+            "--identity",
+            "identity1",
+            "--friend",
+            "friend1",
+            "file.txt.enc",
+        ]));
+        let expected_arg_matches = format!("{:?}", arg_matches.subcommand().1.unwrap());
+        let mut commands = MockCommands::new();
+        commands
+            .expect_forward()
+            .withf(move |arg_matches_param| {
+                expected_arg_matches == format!("{:?}", arg_matches_param)
+            })
+            .returning(|_| Ok(()));
+        let rc_commands: Rc<dyn Commands> = Rc::new(commands);
+        root_exec(rc_commands, &arg_matches)
+    }
+
+    #[test]
+    fn sign() -> Result<(), EncItError> {
+        let cmd = root_cmd();
+        let arg_matches = Rc::new(cmd.get_matches_from(vec![
+            "encit",
+            "sign",
+            "--identity",
+            "identity1",
+            "file.txt",
+        ]));
+        let expected_arg_matches = format!("{:?}", arg_matches.subcommand().1.unwrap());
+        let mut commands = MockCommands::new();
+        commands
+            .expect_sign()
+            .withf(move |arg_matches_param| {
+                expected_arg_matches == format!("{:?}", arg_matches_param)
+            })
+            .returning(|_| Ok(()));
+        let rc_commands: Rc<dyn Commands> = Rc::new(commands);
+        root_exec(rc_commands, &arg_matches)
+    }
+
+    #[test]
+    fn verify() -> Result<(), EncItError> {
+        let cmd = root_cmd();
+        let arg_matches =
+            Rc::new(cmd.get_matches_from(vec!["encit", "verify", "file.txt.sig", "file.txt"]));
+        let expected_arg_matches = format!("{:?}", arg_matches.subcommand().1.unwrap());
+        let mut commands = MockCommands::new();
+        commands
+            .expect_verify()
+            .withf(move |arg_matches_param| {
+                expected_arg_matches == format!("{:?}", arg_matches_param)
+            })
+            .returning(|_| Ok(()));
+        let rc_commands: Rc<dyn Commands> = Rc::new(commands);
+        root_exec(rc_commands, &arg_matches)
+    }
+
+    #[test]
+    fn verify_file_named_friend() -> Result<(), EncItError> {
+        for args in [
+            vec!["encit", "verify", "friend.sig", "friend"],
+            vec!["encit", "verify", "--", "friend"],
+        ] {
+            let arg_matches = Rc::new(root_cmd().get_matches_from(args));
+            let expected_arg_matches = format!("{:?}", arg_matches.subcommand().1.unwrap());
+            let mut commands = MockCommands::new();
+            commands
+                .expect_verify()
+                .withf(move |arg_matches_param| {
+                    expected_arg_matches == format!("{:?}", arg_matches_param)
+                })
+                .returning(|_| Ok(()));
+            let rc_commands: Rc<dyn Commands> = Rc::new(commands);
+            root_exec(rc_commands, &arg_matches)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn verify_friend() -> Result<(), EncItError> {
+        let cmd = root_cmd();
+        let arg_matches = Rc::new(cmd.get_matches_from(vec![
+            "encit",
+            "verify",
+            "friend",
+            "friend1",
+            "--fingerprint",
+            "0001 0203 0405 0607 0809 0A0B 0C0D 0E0F 1011 1213",
+        ]));
+        let expected_arg_matches = format!(
+            "{:?}",
+            arg_matches.subcommand().1.unwrap().subcommand().1.unwrap()
+        );
+        let mut commands = MockCommands::new();
+        commands
+            .expect_verify_friend()
+            .withf(move |arg_matches_param| {
+                expected_arg_matches == format!("{:?}", arg_matches_param)
+            })
+            .returning(|_| Ok(()));
+        let rc_commands: Rc<dyn Commands> = Rc::new(commands);
+        root_exec(rc_commands, &arg_matches)
+    }
+
+    #[test]
+    fn fingerprint() -> Result<(), EncItError> {
+        let cmd = root_cmd();
+        let arg_matches = Rc::new(cmd.get_matches_from(vec!["encit", "fingerprint", "friend1"]));
+        let expected_arg_matches = format!("{:?}", arg_matches.subcommand().1.unwrap());
+        let mut commands = MockCommands::new();
+        commands
+            .expect_fingerprint()
+            .withf(move |arg_matches_param| {
+                expected_arg_matches == format!("{:?}", arg_matches_param)
+            })
+            .returning(|_| Ok(()));
+        let rc_commands: Rc<dyn Commands> = Rc::new(commands);
+        root_exec(rc_commands, &arg_matches)
+    }
+
+    #[test]
+    fn safety_number() -> Result<(), EncItError> {
+        let cmd = root_cmd();
+        let arg_matches = Rc::new(cmd.get_matches_from(vec![
+            "encit",
+            "safety-number",
+            "--identity",
+            "identity1",
+            "--friend",
+            "friend1",
+        ]));
+        let expected_arg_matches = format!("{:?}", arg_matches.subcommand().1.unwrap());
+        let mut commands = MockCommands::new();
+        commands
+            .expect_safety_number()
+            .withf(move |arg_matches_param| {
+                expected_arg_matches == format!("{:?}", arg_matches_param)
+            })
+            .returning(|_| Ok(()));
+        let rc_commands: Rc<dyn Commands> = Rc::new(commands);
+        root_exec(rc_commands, &arg_matches)
+    }
 }
@@ -0,0 +1,99 @@
+use crate::fingerprint::safety_number;
+use crate::{EncItConfig, EncItError};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use std::cell::RefCell;
+use std::io::{stdout, Write};
+use std::rc::Rc;
+
+pub fn safety_number_cmd<'a>() -> App<'a, 'a> {
+    SubCommand::with_name("safety-number")
+        .about("display the code to compare with a friend, both sides get the same code")
+        .arg(
+            Arg::with_name("identity")
+                .long("identity")
+                .short("i")
+                .required(true)
+                .takes_value(true)
+                .help("Identity name (has to be present in the encit configuration file)"),
+        )
+        .arg(
+            Arg::with_name("friend")
+                .long("friend")
+                .short("f")
+                .required(true)
+                .takes_value(true)
+                .help("Friend name (has to be present in the encit configuration file)"),
+        )
+}
+
+pub fn safety_number_exec(
+    cmd_matches: &ArgMatches,
+    config: Rc<dyn EncItConfig>,
+) -> Result<(), EncItError> {
+    safety_number_display(cmd_matches, config, Rc::new(RefCell::new(stdout())))
+}
+
+fn safety_number_display(
+    cmd_matches: &ArgMatches,
+    config: Rc<dyn EncItConfig>,
+    writer: Rc<RefCell<dyn Write>>,
+) -> Result<(), EncItError> {
+    let identity_name = cmd_matches.value_of("identity").unwrap();
+    let friend_name = cmd_matches.value_of("friend").unwrap();
+    let identity = config
+        .identity(identity_name)
+        .ok_or_else(|| EncItError::IdentityNotFound(identity_name.to_string()))?;
+    let friend = config
+        .friend(friend_name)
+        .ok_or_else(|| EncItError::FriendNotFound(friend_name.to_string()))?;
+    let code = safety_number(
+        &identity.private_key().public_key_fingerprint()?,
+        &friend.public_key().fingerprint()?,
+    );
+    writeln!(writer.borrow_mut(), "{}", code)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EncItKeyType;
+    use crate::config::{EncItFriend, EncItIdentity, MockEncItConfig};
+    use crate::enc::tests::{generate_friend, generate_identity};
+
+    fn display(
+        identity: &'static EncItIdentity,
+        friend: &'static EncItFriend,
+    ) -> Result<String, EncItError> {
+        let cmd_matches = safety_number_cmd().get_matches_from(vec![
+            "safety-number",
+            "-i",
+            identity.name(),
+            "-f",
+            friend.name(),
+        ]);
+        let mut cfg = MockEncItConfig::new();
+        cfg.expect_identity().returning(move |_| Some(identity));
+        cfg.expect_friend().returning(move |_| Some(friend));
+        let writer: Rc<RefCell<Vec<u8>>> = Rc::new(RefCell::new(Vec::new()));
+        safety_number_display(&cmd_matches, Rc::new(cfg), writer.clone())?;
+        let result = String::from_utf8(writer.borrow().to_vec())?;
+        Ok(result)
+    }
+
+    #[test]
+    fn same_code_on_both_sides() -> Result<(), EncItError> {
+        let alice_key = EncItKeyType::Ed25519.generate()?;
+        let bob_key = EncItKeyType::EcP384.generate()?;
+        let (_, alice) = generate_identity("alice", Some(alice_key.clone()));
+        let (_, bob_friend) = generate_friend("bob", Some(bob_key.clone()));
+        let (_, bob) = generate_identity("bob", Some(bob_key));
+        let (_, alice_friend) = generate_friend("alice", Some(alice_key));
+
+        let alice_code = display(Box::leak(alice), Box::leak(bob_friend))?;
+        let bob_code = display(Box::leak(bob), Box::leak(alice_friend))?;
+        assert_eq!(alice_code, bob_code);
+        assert_eq!(alice_code.lines().count(), 3);
+        Ok(())
+    }
+}
@@ -0,0 +1,108 @@
+use crate::cmd::reader::{get_file_reader, EncItFileReader};
+use crate::enc::{EncIt, EncItImpl};
+use crate::{EncItConfig, EncItError};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use std::cell::RefCell;
+use std::io::{stdout, Read, Write};
+use std::rc::Rc;
+
+pub fn sign_cmd<'a>() -> App<'a, 'a> {
+    SubCommand::with_name("sign")
+        .about("create a detached signature of a file/text")
+        .arg(
+            Arg::with_name("identity")
+                .long("identity")
+                .short("i")
+                .required(true)
+                .takes_value(true)
+                .help("Identity name (has to be present in the encit configuration file)"),
+        )
+        .arg(
+            Arg::with_name("clear")
+                .long("clear")
+                .help("Output the readable text followed by the attached signature"),
+        )
+        .arg(
+            Arg::with_name("file")
+                .takes_value(true)
+                .help("file to sign"),
+        )
+}
+
+pub fn sign_exec(cmd_matches: &ArgMatches, config: Rc<dyn EncItConfig>) -> Result<(), EncItError> {
+    let enc_it = Rc::new(EncItImpl::new(config));
+    let reader = RefCell::new(get_file_reader(cmd_matches, "file")?);
+    let writer = Rc::new(RefCell::new(stdout()));
+    sign(cmd_matches, enc_it, reader, writer)
+}
+
+fn sign(
+    cmd_matches: &ArgMatches,
+    enc_it: Rc<dyn EncIt>,
+    reader: RefCell<Box<dyn EncItFileReader>>,
+    writer: Rc<RefCell<dyn Write>>,
+) -> Result<(), EncItError> {
+    let identity = cmd_matches.value_of("identity").unwrap();
+    let mut message = Vec::new();
+    reader.borrow_mut().read_to_end(&mut message)?;
+    let mut writer = writer.borrow_mut();
+    if cmd_matches.is_present("clear") {
+        let clear_signed = enc_it.clear_sign(identity, &String::from_utf8(message)?)?;
+        writer.write_all(clear_signed.as_bytes())?;
+        return Ok(());
+    }
+    let signature = enc_it.sign(identity, &message)?;
+    writer.write_all(signature.as_bytes())?;
+    writer.write_all(b"\n")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enc::MockEncIt;
+
+    #[test]
+    fn sign_test() -> Result<(), EncItError> {
+        let cmd = sign_cmd();
+        let cmd_matches = cmd.get_matches_from(vec!["sign", "--identity", "identity-1"]);
+
+        let mut encit_mock = MockEncIt::new();
+        encit_mock
+            .expect_sign()
+            .withf(|identity_param, message_param| {
+                identity_param == "identity-1" && message_param == b"release"
+            })
+            .returning(|_, _| Ok("header..signature".to_string()));
+        let reader = RefCell::new(Box::new("release".as_bytes()));
+
+        let writer: Rc<RefCell<Vec<u8>>> = Rc::new(RefCell::new(Vec::new()));
+        sign(&cmd_matches, Rc::new(encit_mock), reader, writer.clone())?;
+
+        let result = String::from_utf8(writer.borrow().to_vec())?;
+        assert_eq!(result, "header..signature\n");
+        Ok(())
+    }
+
+    #[test]
+    fn clear_sign_test() -> Result<(), EncItError> {
+        let cmd = sign_cmd();
+        let cmd_matches = cmd.get_matches_from(vec!["sign", "--clear", "-i", "identity-1"]);
+
+        let mut encit_mock = MockEncIt::new();
+        encit_mock
+            .expect_clear_sign()
+            .withf(|identity_param, message_param| {
+                identity_param == "identity-1" && message_param == "announcement"
+            })
+            .returning(|_, message| Ok(format!("BEGIN\n{}\nSIGNATURE\n", message)));
+        let reader = RefCell::new(Box::new("announcement".as_bytes()));
+
+        let writer: Rc<RefCell<Vec<u8>>> = Rc::new(RefCell::new(Vec::new()));
+        sign(&cmd_matches, Rc::new(encit_mock), reader, writer.clone())?;
+
+        let result = String::from_utf8(writer.borrow().to_vec())?;
+        assert_eq!(result, "BEGIN\nannouncement\nSIGNATURE\n");
+        Ok(())
+    }
+}
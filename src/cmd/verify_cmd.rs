@@ -0,0 +1,151 @@
+use crate::cmd::reader::{get_file_reader, EncItFileReader};
+use crate::cmd::verify_friend_cmd::verify_friend_cmd;
+use crate::enc::{is_clear_signed, EncIt, EncItDecryptOptions, EncItImpl};
+use crate::{EncItConfig, EncItError};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{stdout, Read, Write};
+use std::rc::Rc;
+
+pub fn verify_cmd<'a>() -> App<'a, 'a> {
+    SubCommand::with_name("verify")
+        .about("verify a detached signature or a clear-signed message with the friends public keys")
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .setting(AppSettings::ArgsNegateSubcommands)
+        .setting(AppSettings::AllowExternalSubcommands)
+        .after_help(
+            "A signature file named friend has to follow --, for example `encit verify -- friend`",
+        )
+        .arg(
+            Arg::with_name("signature")
+                .required(true)
+                .takes_value(true)
+                .help("signature file or clear-signed message"),
+        )
+        .arg(Arg::with_name("file").takes_value(true).help("signed file"))
+        .subcommand(verify_friend_cmd())
+}
+
+pub fn verify_exec(
+    cmd_matches: &ArgMatches,
+    config: Rc<dyn EncItConfig>,
+) -> Result<(), EncItError> {
+    let enc_it = Rc::new(EncItImpl::new(config));
+    let signature_file: Box<dyn EncItFileReader> =
+        Box::new(File::open(cmd_matches.value_of("signature").unwrap())?);
+    let reader = RefCell::new(get_file_reader(cmd_matches, "file")?);
+    let writer = Rc::new(RefCell::new(stdout()));
+    verify(enc_it, RefCell::new(signature_file), reader, writer)
+}
+
+fn verify(
+    enc_it: Rc<dyn EncIt>,
+    signature_reader: RefCell<Box<dyn EncItFileReader>>,
+    reader: RefCell<Box<dyn EncItFileReader>>,
+    writer: Rc<RefCell<dyn Write>>,
+) -> Result<(), EncItError> {
+    let mut signature = String::new();
+    signature_reader
+        .borrow_mut()
+        .read_to_string(&mut signature)?;
+    if is_clear_signed(&signature) {
+        let message = enc_it.decrypt(&signature, None, &EncItDecryptOptions::default())?;
+        writeln!(
+            writer.borrow_mut(),
+            "Good signature from {}",
+            message.sender().unwrap_or_default()
+        )?;
+        return Ok(());
+    }
+    let mut message = Vec::new();
+    reader.borrow_mut().read_to_end(&mut message)?;
+    let signer = enc_it.verify(&signature, &message)?;
+    writeln!(writer.borrow_mut(), "Good signature from {}", signer)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enc::{EncItMessage, MockEncIt};
+
+    #[test]
+    fn verify_test() -> Result<(), EncItError> {
+        let mut encit_mock = MockEncIt::new();
+        encit_mock
+            .expect_verify()
+            .withf(|signature_param, message_param| {
+                signature_param == "header..signature\n" && message_param == b"release"
+            })
+            .returning(|_, _| Ok("friend-1".to_string()));
+        let signature_reader = RefCell::new(Box::new("header..signature\n".as_bytes()));
+        let reader = RefCell::new(Box::new("release".as_bytes()));
+
+        let writer: Rc<RefCell<Vec<u8>>> = Rc::new(RefCell::new(Vec::new()));
+        verify(
+            Rc::new(encit_mock),
+            signature_reader,
+            reader,
+            writer.clone(),
+        )?;
+
+        let result = String::from_utf8(writer.borrow().to_vec())?;
+        assert_eq!(result, "Good signature from friend-1\n");
+        Ok(())
+    }
+
+    #[test]
+    fn verify_clear_signed() -> Result<(), EncItError> {
+        let clear_signed = "-----BEGIN ENCIT SIGNED MESSAGE-----\nhello\n";
+        let mut encit_mock = MockEncIt::new();
+        encit_mock
+            .expect_decrypt()
+            .withf(move |message_param, opt_identity, _| {
+                message_param == clear_signed && opt_identity.is_none()
+            })
+            .returning(|_, _, _| {
+                Ok(EncItMessage::new(
+                    Some("friend-1".to_string()),
+                    String::new(),
+                    None,
+                    b"hello".to_vec(),
+                    true,
+                ))
+            });
+        let signature_reader = RefCell::new(Box::new(clear_signed.as_bytes()));
+        let reader = RefCell::new(Box::new("".as_bytes()));
+
+        let writer: Rc<RefCell<Vec<u8>>> = Rc::new(RefCell::new(Vec::new()));
+        verify(
+            Rc::new(encit_mock),
+            signature_reader,
+            reader,
+            writer.clone(),
+        )?;
+
+        let result = String::from_utf8(writer.borrow().to_vec())?;
+        assert_eq!(result, "Good signature from friend-1\n");
+        Ok(())
+    }
+
+    #[test]
+    fn verify_invalid_signature() {
+        let mut encit_mock = MockEncIt::new();
+        encit_mock
+            .expect_verify()
+            .returning(|_, _| Err(EncItError::InvalidSignature()));
+        let signature_reader = RefCell::new(Box::new("header..signature".as_bytes()));
+        let reader = RefCell::new(Box::new("tampered".as_bytes()));
+
+        let writer: Rc<RefCell<Vec<u8>>> = Rc::new(RefCell::new(Vec::new()));
+        let result = verify(
+            Rc::new(encit_mock),
+            signature_reader,
+            reader,
+            writer.clone(),
+        );
+        assert!(matches!(result, Err(EncItError::InvalidSignature())));
+        assert!(writer.borrow().is_empty());
+    }
+}
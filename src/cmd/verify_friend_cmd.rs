@@ -0,0 +1,181 @@
+use crate::config::EncItTrustLevel;
+use crate::fingerprint::matches_fingerprint;
+use crate::{EncItConfig, EncItError};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use std::cell::RefCell;
+use std::io::{stdout, Write};
+use std::rc::Rc;
+
+pub fn verify_friend_cmd<'a>() -> App<'a, 'a> {
+    SubCommand::with_name("friend")
+        .about("mark a friend key as verified once its fingerprint has been compared")
+        .arg(
+            Arg::with_name("name")
+                .takes_value(true)
+                .required(true)
+                .help("Friend name"),
+        )
+        .arg(
+            Arg::with_name("fingerprint")
+                .long("fingerprint")
+                .takes_value(true)
+                .required_unless("revoke")
+                .help("Fingerprint read by the friend (grouped hex, numeric or thumbprint)"),
+        )
+        .arg(
+            Arg::with_name("revoke")
+                .long("revoke")
+                .conflicts_with("fingerprint")
+                .help("Revoke the friend key, no message is encrypted for a revoked friend"),
+        )
+}
+
+pub fn verify_friend_exec(
+    cmd_matches: &ArgMatches,
+    config: Rc<dyn EncItConfig>,
+) -> Result<(), EncItError> {
+    verify_friend(cmd_matches, config, Rc::new(RefCell::new(stdout())))
+}
+
+fn verify_friend(
+    cmd_matches: &ArgMatches,
+    config: Rc<dyn EncItConfig>,
+    writer: Rc<RefCell<dyn Write>>,
+) -> Result<(), EncItError> {
+    let friend_name = cmd_matches.value_of("name").unwrap();
+    let friend = config
+        .friend(friend_name)
+        .ok_or_else(|| EncItError::FriendNotFound(friend_name.to_string()))?;
+    let trust = match cmd_matches.value_of("fingerprint") {
+        Some(fingerprint) => {
+            // the revocation is sticky, the compromised key keeps its fingerprint
+            if friend.trust() == EncItTrustLevel::Revoked {
+                return Err(EncItError::FriendRevoked(friend_name.to_string()));
+            }
+            if !matches_fingerprint(&friend.public_key().fingerprint()?, fingerprint) {
+                return Err(EncItError::FingerprintMismatch(friend_name.to_string()));
+            }
+            EncItTrustLevel::Verified
+        }
+        None => EncItTrustLevel::Revoked,
+    };
+    config.set_friend_trust(friend_name, trust)?.save()?;
+    writeln!(writer.borrow_mut(), "friend {} {}", friend_name, trust)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{EncItFriend, MockEncItConfig};
+    use crate::enc::tests::generate_friend;
+    use crate::fingerprint::{grouped_fingerprint, public_key_fingerprint};
+    use mockall::predicate::eq;
+
+    fn friend_config(friend: &'static EncItFriend) -> MockEncItConfig {
+        let mut cfg = MockEncItConfig::new();
+        cfg.expect_friend()
+            .returning(move |name| Some(friend).filter(|friend| friend.name() == name));
+        cfg
+    }
+
+    #[test]
+    fn verify_friend_fingerprint() -> Result<(), EncItError> {
+        let (bob_private_key, bob) = generate_friend("bob", None);
+        let bob: &'static EncItFriend = Box::leak(bob);
+        let fingerprint = grouped_fingerprint(&public_key_fingerprint(&bob_private_key)?);
+        let cmd_matches = verify_friend_cmd().get_matches_from(vec![
+            "friend",
+            "bob",
+            "--fingerprint",
+            &fingerprint,
+        ]);
+        let mut cfg = friend_config(bob);
+        cfg.expect_set_friend_trust()
+            .with(eq("bob"), eq(EncItTrustLevel::Verified))
+            .times(1)
+            .returning(|_, _| {
+                let mut new_cfg = MockEncItConfig::new();
+                new_cfg.expect_save().times(1).returning(|| Ok(()));
+                Ok(Box::new(new_cfg))
+            });
+        let writer: Rc<RefCell<Vec<u8>>> = Rc::new(RefCell::new(Vec::new()));
+        verify_friend(&cmd_matches, Rc::new(cfg), writer.clone())?;
+        assert_eq!(
+            String::from_utf8(writer.borrow().to_vec())?,
+            "friend bob verified\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn verify_friend_mismatch() -> Result<(), EncItError> {
+        let (_, bob) = generate_friend("bob", None);
+        let bob: &'static EncItFriend = Box::leak(bob);
+        let (other_private_key, _) = generate_friend("other", None);
+        let fingerprint = grouped_fingerprint(&public_key_fingerprint(&other_private_key)?);
+        let cmd_matches = verify_friend_cmd().get_matches_from(vec![
+            "friend",
+            "bob",
+            "--fingerprint",
+            &fingerprint,
+        ]);
+        let mut cfg = friend_config(bob);
+        cfg.expect_set_friend_trust().never();
+        let writer: Rc<RefCell<Vec<u8>>> = Rc::new(RefCell::new(Vec::new()));
+        assert!(matches!(
+            verify_friend(&cmd_matches, Rc::new(cfg), writer),
+            Err(EncItError::FingerprintMismatch(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn verify_revoked_friend() -> Result<(), EncItError> {
+        let (bob_private_key, bob) = generate_friend("bob", None);
+        let mut bob = serde_json::to_value(&*bob)?;
+        bob["trust"] = serde_json::json!("revoked");
+        let bob: &'static EncItFriend = Box::leak(Box::new(serde_json::from_value(bob)?));
+        let fingerprint = grouped_fingerprint(&public_key_fingerprint(&bob_private_key)?);
+        let cmd_matches = verify_friend_cmd().get_matches_from(vec![
+            "friend",
+            "bob",
+            "--fingerprint",
+            &fingerprint,
+        ]);
+        let mut cfg = friend_config(bob);
+        cfg.expect_set_friend_trust().never();
+        let writer: Rc<RefCell<Vec<u8>>> = Rc::new(RefCell::new(Vec::new()));
+        assert!(matches!(
+            verify_friend(&cmd_matches, Rc::new(cfg), writer),
+            Err(EncItError::FriendRevoked(name)) if name == "bob"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn revoke_friend() -> Result<(), EncItError> {
+        let (_, bob) = generate_friend("bob", None);
+        let bob: &'static EncItFriend = Box::leak(bob);
+        let cmd_matches = verify_friend_cmd().get_matches_from(vec!["friend", "bob", "--revoke"]);
+        let mut cfg = friend_config(bob);
+        cfg.expect_set_friend_trust()
+            .with(eq("bob"), eq(EncItTrustLevel::Revoked))
+            .times(1)
+            .returning(|_, _| {
+                let mut new_cfg = MockEncItConfig::new();
+                new_cfg.expect_save().times(1).returning(|| Ok(()));
+                Ok(Box::new(new_cfg))
+            });
+        let writer: Rc<RefCell<Vec<u8>>> = Rc::new(RefCell::new(Vec::new()));
+        verify_friend(&cmd_matches, Rc::new(cfg), writer.clone())?;
+        assert_eq!(
+            String::from_utf8(writer.borrow().to_vec())?,
+            "friend bob revoked\n"
+        );
+        assert!(verify_friend_cmd()
+            .get_matches_from_safe(vec!["friend", "bob"])
+            .is_err());
+        Ok(())
+    }
+}
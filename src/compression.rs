@@ -0,0 +1,116 @@
+use std::io::{self, Read, Write};
+
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use josekit::jwe::JweCompression;
+
+use crate::errors::EncItError;
+
+/// JWE "zip" header value of the DEFLATE compression (RFC 7516)
+pub const DEFLATE: &str = "DEF";
+/// decrypted messages are kept in memory, bigger files have to be sent as stream
+pub const MAX_DECOMPRESSED_LEN: usize = 256 * 1024 * 1024;
+/// bytes compressed by the automatic mode to estimate the compression ratio
+const AUTO_SAMPLE_LEN: usize = 64 * 1024;
+/// the automatic mode compresses only if the sample shrinks below this percentage
+const AUTO_MAX_RATIO: usize = 90;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum EncItCompression {
+    #[default]
+    Never,
+    Always,
+    /// compress only the messages that shrink enough (e.g. logs, CSV)
+    Auto,
+}
+
+impl EncItCompression {
+    /// true if the message has to be compressed before the encryption
+    pub fn compress(&self, message: &[u8]) -> Result<bool, EncItError> {
+        match self {
+            EncItCompression::Never => Ok(false),
+            EncItCompression::Always => Ok(true),
+            EncItCompression::Auto => {
+                let sample = &message[..message.len().min(AUTO_SAMPLE_LEN)];
+                if sample.is_empty() {
+                    return Ok(false);
+                }
+                let compressed = BoundedDeflate::new(MAX_DECOMPRESSED_LEN).compress(sample)?;
+                Ok(compressed.len() * 100 < sample.len() * AUTO_MAX_RATIO)
+            }
+        }
+    }
+}
+
+/// DEFLATE with a limit on the decompressed size, so that a hostile message
+/// cannot be used as a decompression bomb
+#[derive(Debug, Clone)]
+pub struct BoundedDeflate {
+    max_len: usize,
+}
+
+impl BoundedDeflate {
+    pub fn new(max_len: usize) -> Self {
+        BoundedDeflate { max_len }
+    }
+}
+
+impl JweCompression for BoundedDeflate {
+    fn name(&self) -> &str {
+        DEFLATE
+    }
+
+    fn compress(&self, message: &[u8]) -> Result<Vec<u8>, io::Error> {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(message)?;
+        encoder.finish()
+    }
+
+    fn decompress(&self, message: &[u8]) -> Result<Vec<u8>, io::Error> {
+        let mut decompressed = Vec::new();
+        DeflateDecoder::new(message)
+            .take(self.max_len as u64 + 1)
+            .read_to_end(&mut decompressed)?;
+        if decompressed.len() > self.max_len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("the decompressed message exceeds {} bytes", self.max_len),
+            ));
+        }
+        Ok(decompressed)
+    }
+
+    fn box_clone(&self) -> Box<dyn JweCompression> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::rand::rand_bytes;
+
+    #[test]
+    fn decompression_limit() -> Result<(), EncItError> {
+        let deflate = BoundedDeflate::new(1024);
+        let compressed = deflate.compress(&[0u8; 1024])?;
+        assert_eq!(deflate.decompress(&compressed)?, vec![0u8; 1024]);
+        let bomb = deflate.compress(&[0u8; 1025])?;
+        assert!(deflate.decompress(&bomb).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn auto_compression() -> Result<(), EncItError> {
+        let csv = "date,level,message\n2021-12-01,INFO,started\n".repeat(100);
+        assert!(EncItCompression::Auto.compress(csv.as_bytes())?);
+        let mut random = vec![0u8; 4096];
+        rand_bytes(&mut random)?;
+        assert!(!EncItCompression::Auto.compress(&random)?);
+        assert!(!EncItCompression::Auto.compress(&[])?);
+        assert!(EncItCompression::Always.compress(&random)?);
+        assert!(!EncItCompression::Never.compress(csv.as_bytes())?);
+        Ok(())
+    }
+}
@@ -1,18 +1,223 @@
+use std::fmt;
 use std::fs;
 use std::fs::read_to_string;
 use std::io::Write;
 use std::path::Path;
+use std::time::SystemTime;
 
 use config::{Config, File};
-use log::debug;
+use josekit::jwk::Jwk;
+use josekit::Value;
+use log::{debug, warn};
 #[cfg(test)]
 use mockall::{automock, predicate::*};
-use openssl::pkey::{Private, Public};
+use openssl::bn::{BigNum, BigNumContext};
+use openssl::ec::{EcGroup, EcKey};
+use openssl::nid::Nid;
+use openssl::pkey::{HasPublic, Id, PKey, Private, Public};
 use openssl::rsa::Rsa;
-use openssl::sha::Sha256;
+use openssl::sha::{sha512, Sha256};
+use openssl::symm::Cipher;
 use serde::{Deserialize, Serialize};
 
 use crate::errors::EncItError;
+use crate::fingerprint::{key_id, public_key_fingerprint};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EncItKeyType {
+    Rsa,
+    EcP256,
+    EcP384,
+    Ed25519,
+}
+
+impl EncItKeyType {
+    pub const NAMES: [&'static str; 4] = ["rsa", "ec-p256", "ec-p384", "ed25519"];
+
+    pub fn from_name(name: &str) -> Result<Self, EncItError> {
+        match name {
+            "rsa" => Ok(EncItKeyType::Rsa),
+            "ec-p256" => Ok(EncItKeyType::EcP256),
+            "ec-p384" => Ok(EncItKeyType::EcP384),
+            "ed25519" => Ok(EncItKeyType::Ed25519),
+            _ => Err(EncItError::SSLError(format!(
+                "unsupported key type {}",
+                name
+            ))),
+        }
+    }
+
+    pub fn from_key<T: HasPublic>(key: &PKey<T>) -> Result<Self, EncItError> {
+        match key.id() {
+            Id::RSA => Ok(EncItKeyType::Rsa),
+            Id::EC => match key.ec_key()?.group().curve_name() {
+                Some(Nid::X9_62_PRIME256V1) => Ok(EncItKeyType::EcP256),
+                Some(Nid::SECP384R1) => Ok(EncItKeyType::EcP384),
+                curve => Err(EncItError::SSLError(format!(
+                    "unsupported elliptic curve {:?}",
+                    curve
+                ))),
+            },
+            Id::ED25519 => Ok(EncItKeyType::Ed25519),
+            id => Err(EncItError::SSLError(format!(
+                "unsupported key type {:?}",
+                id
+            ))),
+        }
+    }
+
+    pub fn generate(&self) -> Result<PKey<Private>, EncItError> {
+        let key = match self {
+            EncItKeyType::Rsa => PKey::from_rsa(Rsa::generate(2048)?)?,
+            EncItKeyType::EcP256 => PKey::from_ec_key(EcKey::generate(
+                EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?.as_ref(),
+            )?)?,
+            EncItKeyType::EcP384 => PKey::from_ec_key(EcKey::generate(
+                EcGroup::from_curve_name(Nid::SECP384R1)?.as_ref(),
+            )?)?,
+            EncItKeyType::Ed25519 => PKey::generate_ed25519()?,
+        };
+        Ok(key)
+    }
+
+    /// serialize an unencrypted private key, RSA keys keep the traditional (PKCS#1) format
+    pub fn private_key_to_pem(key: &PKey<Private>) -> Result<Vec<u8>, EncItError> {
+        match Self::from_key(key)? {
+            EncItKeyType::Rsa => key.rsa()?.private_key_to_pem(),
+            _ => key.private_key_to_pem_pkcs8(),
+        }
+        .map_err(|e| e.into())
+    }
+
+    /// encrypted PKCS#8 PEM of any key type
+    pub fn private_key_to_pem_passphrase(
+        key: &PKey<Private>,
+        passphrase: &str,
+    ) -> Result<Vec<u8>, EncItError> {
+        key.private_key_to_pem_pkcs8_passphrase(Cipher::aes_256_cbc(), passphrase.as_bytes())
+            .map_err(|e| e.into())
+    }
+}
+
+/// Ed25519 keys are only able to sign, messages are encrypted to the X25519 key
+/// of the same curve (the conversion is the one used by libsodium)
+fn x25519_private_key(ed25519_key: &PKey<Private>) -> Result<PKey<Private>, EncItError> {
+    let mut scalar = sha512(ed25519_key.raw_private_key()?.as_slice())[..32].to_vec();
+    scalar[0] &= 248;
+    scalar[31] &= 127;
+    scalar[31] |= 64;
+    PKey::private_key_from_raw_bytes(&scalar, Id::X25519).map_err(|e| e.into())
+}
+
+/// birational map from the Edwards y coordinate to the Montgomery u: u = (1 + y) / (1 - y)
+fn x25519_public_key<T: HasPublic>(ed25519_key: &PKey<T>) -> Result<PKey<Public>, EncItError> {
+    let mut ctx = BigNumContext::new()?;
+    let p =
+        BigNum::from_hex_str("7fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffed")?;
+    let one = BigNum::from_u32(1)?;
+    let mut y_bytes = ed25519_key.raw_public_key()?;
+    y_bytes[31] &= 127;
+    y_bytes.reverse();
+    let y = BigNum::from_slice(&y_bytes)?;
+
+    let mut numerator = BigNum::new()?;
+    numerator.mod_add(&one, &y, &p, &mut ctx)?;
+    let mut denominator = BigNum::new()?;
+    denominator.mod_sub(&one, &y, &p, &mut ctx)?;
+    let mut denominator_inverse = BigNum::new()?;
+    denominator_inverse.mod_inverse(&denominator, &p, &mut ctx)?;
+    let mut u = BigNum::new()?;
+    u.mod_mul(&numerator, &denominator_inverse, &p, &mut ctx)?;
+
+    let mut u_bytes = u.to_vec_padded(32)?;
+    u_bytes.reverse();
+    PKey::public_key_from_raw_bytes(&u_bytes, Id::X25519).map_err(|e| e.into())
+}
+
+fn b64_param(value: &[u8]) -> Option<Value> {
+    Some(Value::String(base64::encode_config(
+        value,
+        base64::URL_SAFE_NO_PAD,
+    )))
+}
+
+pub fn public_key_to_jwk<T: HasPublic>(key: &PKey<T>) -> Result<Jwk, EncItError> {
+    let key_type = EncItKeyType::from_key(key)?;
+    let jwk = match key_type {
+        EncItKeyType::Rsa => {
+            let rsa = key.rsa()?;
+            let mut jwk = Jwk::new("RSA");
+            jwk.set_parameter("n", b64_param(&rsa.n().to_vec()))?;
+            jwk.set_parameter("e", b64_param(&rsa.e().to_vec()))?;
+            jwk
+        }
+        EncItKeyType::EcP256 | EncItKeyType::EcP384 => {
+            let (curve, coordinate_len) = if key_type == EncItKeyType::EcP256 {
+                ("P-256", 32)
+            } else {
+                ("P-384", 48)
+            };
+            let ec_key = key.ec_key()?;
+            let mut ctx = BigNumContext::new()?;
+            let mut x = BigNum::new()?;
+            let mut y = BigNum::new()?;
+            ec_key
+                .public_key()
+                .affine_coordinates_gfp(ec_key.group(), &mut x, &mut y, &mut ctx)?;
+            let mut jwk = Jwk::new("EC");
+            jwk.set_curve(curve);
+            jwk.set_parameter("x", b64_param(&x.to_vec_padded(coordinate_len)?))?;
+            jwk.set_parameter("y", b64_param(&y.to_vec_padded(coordinate_len)?))?;
+            jwk
+        }
+        EncItKeyType::Ed25519 => {
+            let mut jwk = Jwk::new("OKP");
+            jwk.set_curve("Ed25519");
+            jwk.set_parameter("x", b64_param(&key.raw_public_key()?))?;
+            jwk
+        }
+    };
+    Ok(jwk)
+}
+
+fn jwk_param(jwk: &Jwk, name: &str) -> Result<Vec<u8>, EncItError> {
+    let value = jwk
+        .parameter(name)
+        .and_then(|value| value.as_str())
+        .ok_or_else(|| EncItError::DecodeError(format!("missing jwk parameter {}", name)))?;
+    base64::decode_config(value, base64::URL_SAFE_NO_PAD).map_err(|e| e.into())
+}
+
+fn jwk_to_public_key(jwk: &Jwk) -> Result<PKey<Public>, EncItError> {
+    let key = match (jwk.key_type(), jwk.curve()) {
+        ("RSA", _) => PKey::from_rsa(Rsa::from_public_components(
+            BigNum::from_slice(&jwk_param(jwk, "n")?)?,
+            BigNum::from_slice(&jwk_param(jwk, "e")?)?,
+        )?)?,
+        ("EC", Some(curve @ ("P-256" | "P-384"))) => {
+            let nid = if curve == "P-256" {
+                Nid::X9_62_PRIME256V1
+            } else {
+                Nid::SECP384R1
+            };
+            let group = EcGroup::from_curve_name(nid)?;
+            let x = BigNum::from_slice(&jwk_param(jwk, "x")?)?;
+            let y = BigNum::from_slice(&jwk_param(jwk, "y")?)?;
+            PKey::from_ec_key(EcKey::from_public_key_affine_coordinates(&group, &x, &y)?)?
+        }
+        ("OKP", Some("Ed25519")) => {
+            PKey::public_key_from_raw_bytes(&jwk_param(jwk, "x")?, Id::ED25519)?
+        }
+        (key_type, curve) => {
+            return Err(EncItError::DecodeError(format!(
+                "unsupported jwk {} {}",
+                key_type,
+                curve.unwrap_or_default()
+            )))
+        }
+    };
+    Ok(key)
+}
 
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
 #[serde(rename_all = "lowercase")]
@@ -24,6 +229,12 @@ pub enum EncItPEM {
 }
 
 impl EncItPEM {
+    pub fn from_jwk(jwk: &Jwk) -> Result<Self, EncItError> {
+        Ok(EncItPEM::Hex(hex::encode(
+            jwk_to_public_key(jwk)?.public_key_to_pem()?,
+        )))
+    }
+
     pub fn pem(&self) -> Result<Vec<u8>, EncItError> {
         match self {
             EncItPEM::Path(path) => {
@@ -43,15 +254,42 @@ impl EncItPEM {
         }
     }
 
-    pub fn public_key(&self) -> Result<Rsa<Public>, EncItError> {
-        Rsa::public_key_from_pem(self.pem()?.as_slice()).map_err(|e| e.into())
+    /// SubjectPublicKeyInfo or PKCS#1 RSA public key
+    pub fn public_key(&self) -> Result<PKey<Public>, EncItError> {
+        let pem = self.pem()?;
+        match PKey::public_key_from_pem(pem.as_slice()) {
+            Ok(public_key) => Ok(public_key),
+            Err(e) => Rsa::public_key_from_pem_pkcs1(pem.as_slice())
+                .and_then(PKey::from_rsa)
+                .map_err(|_| e.into()),
+        }
+    }
+
+    /// the public key PEM as written by OpenSSL, whatever the stored format
+    pub fn public_key_pem(&self) -> Result<Vec<u8>, EncItError> {
+        self.public_key()?.public_key_to_pem().map_err(|e| e.into())
+    }
+
+    pub fn key_type(&self) -> Result<EncItKeyType, EncItError> {
+        EncItKeyType::from_key(&self.public_key()?)
+    }
+
+    /// public key used to encrypt messages
+    pub fn encryption_key_pem(&self) -> Result<Vec<u8>, EncItError> {
+        let public_key = self.public_key()?;
+        match EncItKeyType::from_key(&public_key)? {
+            EncItKeyType::Ed25519 => x25519_public_key(&public_key)?
+                .public_key_to_pem()
+                .map_err(|e| e.into()),
+            _ => self.public_key_pem(),
+        }
     }
 
-    pub fn private_key(&self, passphrase: Option<&str>) -> Result<Rsa<Private>, EncItError> {
+    pub fn private_key(&self, passphrase: Option<&str>) -> Result<PKey<Private>, EncItError> {
         if let Some(passphrase) = passphrase {
-            Rsa::private_key_from_pem_passphrase(self.pem()?.as_slice(), passphrase.as_bytes())
+            PKey::private_key_from_pem_passphrase(self.pem()?.as_slice(), passphrase.as_bytes())
         } else {
-            Rsa::private_key_from_pem(self.pem()?.as_slice())
+            PKey::private_key_from_pem(self.pem()?.as_slice())
         }
         .map_err(|e| e.into())
     }
@@ -65,6 +303,29 @@ impl EncItPEM {
         sha.update(self.pem()?.as_slice());
         Ok(hex::encode(sha.finish()))
     }
+
+    /// fingerprint of the public key to compare by eye
+    pub fn fingerprint(&self) -> Result<Vec<u8>, EncItError> {
+        public_key_fingerprint(&self.public_key()?)
+    }
+
+    /// JWK thumbprint of the public key, used as key id in the messages
+    pub fn thumbprint(&self) -> Result<String, EncItError> {
+        Ok(key_id(&self.fingerprint()?))
+    }
+
+    /// The ids the key is known by: the thumbprint and, for the messages of older versions, the
+    /// sha of the PEM as written by OpenSSL and of the PEM as stored
+    pub fn key_ids(&self) -> Result<Vec<String>, EncItError> {
+        let mut sha = Sha256::new();
+        sha.update(&self.public_key_pem()?);
+        let mut key_ids = vec![self.thumbprint()?, hex::encode(sha.finish())];
+        let stored_sha = self.sha_pem()?;
+        if !key_ids.contains(&stored_sha) {
+            key_ids.push(stored_sha);
+        }
+        Ok(key_ids)
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -81,28 +342,45 @@ impl EncItPrivateKey {
         EncItPrivateKey { key, password }
     }
 
-    pub fn rsa_key(&self) -> Result<Rsa<Private>, EncItError> {
-        let rsa_key = if let Some(password) = &self.password {
-            Rsa::private_key_from_pem_passphrase(self.key.pem()?.as_slice(), password.as_bytes())?
-        } else {
-            Rsa::private_key_from_pem(self.key.pem()?.as_slice())?
-        };
-        Ok(rsa_key)
+    pub fn key(&self) -> Result<PKey<Private>, EncItError> {
+        self.key.private_key(self.password.as_deref())
+    }
+
+    /// passphrase of the encrypted PEM
+    pub fn password(&self) -> Option<&str> {
+        self.password.as_deref()
+    }
+
+    pub fn key_type(&self) -> Result<EncItKeyType, EncItError> {
+        EncItKeyType::from_key(&self.key()?)
     }
 
     pub fn pem(&self) -> Result<Vec<u8>, EncItError> {
-        self.rsa_key()?.private_key_to_pem().map_err(|e| e.into())
+        EncItKeyType::private_key_to_pem(&self.key()?)
+    }
+
+    /// private key used to decrypt messages
+    pub fn encryption_key_pem(&self) -> Result<Vec<u8>, EncItError> {
+        let key = self.key()?;
+        match EncItKeyType::from_key(&key)? {
+            EncItKeyType::Ed25519 => x25519_private_key(&key)?
+                .private_key_to_pem_pkcs8()
+                .map_err(|e| e.into()),
+            _ => EncItKeyType::private_key_to_pem(&key),
+        }
     }
 
     pub fn hex(&self) -> Result<String, EncItError> {
-        self.rsa_key()?
-            .private_key_to_pem()
-            .map(hex::encode)
-            .map_err(|e| e.into())
+        self.pem().map(hex::encode)
     }
 
     pub fn public_key_pem(&self) -> Result<Vec<u8>, EncItError> {
-        self.rsa_key()?.public_key_to_pem().map_err(|e| e.into())
+        self.key()?.public_key_to_pem().map_err(|e| e.into())
+    }
+
+    /// public key in JWK format (RFC 7517)
+    pub fn public_key_jwk(&self) -> Result<Jwk, EncItError> {
+        public_key_to_jwk(&self.key()?)
     }
 
     pub fn public_key_pem_hex(&self) -> Result<String, EncItError> {
@@ -114,6 +392,24 @@ impl EncItPrivateKey {
         sha.update(self.public_key_pem()?.as_slice());
         Ok(hex::encode(sha.finish()))
     }
+
+    /// fingerprint of the public key to compare by eye
+    pub fn public_key_fingerprint(&self) -> Result<Vec<u8>, EncItError> {
+        public_key_fingerprint(&self.key()?)
+    }
+
+    /// JWK thumbprint of the public key, used as key id in the messages
+    pub fn public_key_thumbprint(&self) -> Result<String, EncItError> {
+        Ok(key_id(&self.public_key_fingerprint()?))
+    }
+
+    /// the thumbprint and the sha of the public key PEM used by older versions
+    pub fn key_ids(&self) -> Result<Vec<String>, EncItError> {
+        Ok(vec![
+            self.public_key_thumbprint()?,
+            self.public_key_pem_sha()?,
+        ])
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -121,12 +417,23 @@ pub struct EncItIdentity {
     name: String,
     #[serde(rename = "privateKey")]
     private_key: EncItPrivateKey,
+    /// keys replaced by `rotate identity`, kept to decrypt the older messages
+    #[serde(
+        rename = "previousKeys",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    previous_keys: Vec<EncItPrivateKey>,
 }
 
 #[allow(dead_code)]
 impl EncItIdentity {
     pub fn new(name: String, private_key: EncItPrivateKey) -> Self {
-        EncItIdentity { name, private_key }
+        EncItIdentity {
+            name,
+            private_key,
+            previous_keys: vec![],
+        }
     }
 
     pub fn name(&self) -> &str {
@@ -135,6 +442,50 @@ impl EncItIdentity {
     pub fn private_key(&self) -> &EncItPrivateKey {
         &self.private_key
     }
+    pub fn previous_keys(&self) -> &Vec<EncItPrivateKey> {
+        &self.previous_keys
+    }
+
+    /// the current key first, then the previous ones
+    pub fn keys(&self) -> impl Iterator<Item = &EncItPrivateKey> {
+        std::iter::once(&self.private_key).chain(self.previous_keys.iter())
+    }
+
+    /// key ids of the current and previous keys
+    pub fn key_ids(&self) -> Result<Vec<String>, EncItError> {
+        let mut key_ids = Vec::new();
+        for key in self.keys() {
+            key_ids.extend(key.key_ids()?);
+        }
+        Ok(key_ids)
+    }
+}
+
+/// how much a friend key is trusted, the friends added before the trust levels are unverified
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum EncItTrustLevel {
+    /// imported from a file or stdin, the fingerprint was not checked
+    #[default]
+    Unverified,
+    /// imported from the key embedded in a message (trust on first use)
+    Tofu,
+    /// the fingerprint was compared with the friend
+    Verified,
+    /// the key must not be used anymore
+    Revoked,
+}
+
+impl fmt::Display for EncItTrustLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            EncItTrustLevel::Unverified => "unverified",
+            EncItTrustLevel::Tofu => "tofu",
+            EncItTrustLevel::Verified => "verified",
+            EncItTrustLevel::Revoked => "revoked",
+        };
+        write!(f, "{}", name)
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -142,12 +493,22 @@ pub struct EncItFriend {
     name: String,
     #[serde(rename = "publicKey")]
     public_key: EncItPEM,
+    #[serde(default)]
+    trust: EncItTrustLevel,
+    /// RFC 3339 date of the fingerprint verification
+    #[serde(rename = "verifiedAt", skip_serializing_if = "Option::is_none")]
+    verified_at: Option<String>,
 }
 
 #[allow(dead_code)]
 impl EncItFriend {
     pub fn new(name: String, public_key: EncItPEM) -> Self {
-        EncItFriend { name, public_key }
+        EncItFriend {
+            name,
+            public_key,
+            trust: EncItTrustLevel::default(),
+            verified_at: None,
+        }
     }
     pub fn name(&self) -> &str {
         &self.name
@@ -155,25 +516,34 @@ impl EncItFriend {
     pub fn public_key(&self) -> &EncItPEM {
         &self.public_key
     }
+    pub fn trust(&self) -> EncItTrustLevel {
+        self.trust
+    }
+    pub fn verified_at(&self) -> Option<&str> {
+        self.verified_at.as_deref()
+    }
 }
 
 #[cfg_attr(test, automock)]
 pub trait EncItConfig {
     fn identity<'a>(&'a self, identity: &str) -> Option<&'a EncItIdentity>;
-    fn identity_by_public_key_sha<'a>(
-        &'a self,
-        identity_public_key_sha: &str,
-    ) -> Option<&'a EncItIdentity>;
+    /// the key id is the JWK thumbprint or the public key sha of older versions
+    fn identity_by_key_id<'a>(&'a self, key_id: &str) -> Option<&'a EncItIdentity>;
     fn friend<'a>(&'a self, friend: &str) -> Option<&'a EncItFriend>;
-    fn friend_by_public_key_sha<'a>(
-        &'a self,
-        identity_public_key_hex: &str,
-    ) -> Option<&'a EncItFriend>;
+    /// the key id is the JWK thumbprint or the public key sha of older versions
+    fn friend_by_key_id<'a>(&'a self, key_id: &str) -> Option<&'a EncItFriend>;
     fn friends(&self) -> &Vec<EncItFriend>;
     fn add_friend(
         &self,
         friend_name: &str,
         public_key: &EncItPEM,
+        trust: EncItTrustLevel,
+    ) -> Result<Box<dyn EncItConfig>, EncItError>;
+    /// the verification date is set when the friend is verified
+    fn set_friend_trust(
+        &self,
+        friend_name: &str,
+        trust: EncItTrustLevel,
     ) -> Result<Box<dyn EncItConfig>, EncItError>;
     fn add_identity<'a>(
         &self,
@@ -181,8 +551,22 @@ pub trait EncItConfig {
         private_key: &EncItPEM,
         passphrase: Option<&'a str>,
     ) -> Result<Box<dyn EncItConfig>, EncItError>;
+    /// the current key is moved to the previous keys, to decrypt the older messages.
+    /// The new key PEM has to be encrypted with the passphrase of the current key
+    fn rotate_identity(
+        &self,
+        identity_name: &str,
+        private_key: &EncItPEM,
+    ) -> Result<Box<dyn EncItConfig>, EncItError>;
+    /// new key of a friend after a verified transition statement, the trust level is kept
+    fn set_friend_key(
+        &self,
+        friend_name: &str,
+        public_key: &EncItPEM,
+    ) -> Result<Box<dyn EncItConfig>, EncItError>;
     fn identities(&self) -> &Vec<EncItIdentity>;
     fn save(&self) -> Result<(), EncItError>;
+    fn path(&self) -> &str;
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -228,14 +612,23 @@ impl EncItConfig for EncItConfigImpl {
         self.identities.iter().find(|i| i.name == identity)
     }
 
-    fn identity_by_public_key_sha(&self, identity_public_key_sha: &str) -> Option<&EncItIdentity> {
+    fn identity_by_key_id(&self, key_id: &str) -> Option<&EncItIdentity> {
         self.identities.iter().find(|identity| {
-            let pub_key_sha = identity
-                .private_key
-                .public_key_pem_sha()
-                .unwrap_or_else(|_| panic!("cannot get public key for identity {}", identity.name));
-            debug!("checking {} with {}", pub_key_sha, identity_public_key_sha);
-            pub_key_sha == identity_public_key_sha
+            // an unsupported key does not match, the other identities are still usable
+            let key_ids = match identity.key_ids() {
+                Ok(key_ids) => key_ids,
+                Err(e) => {
+                    warn!(
+                        "cannot get the key ids of identity {}: {}",
+                        identity.name, e
+                    );
+                    return false;
+                }
+            };
+            debug!("checking {:?} with {}", key_ids, key_id);
+            key_ids
+                .iter()
+                .any(|identity_key_id| identity_key_id == key_id)
         })
     }
 
@@ -243,13 +636,17 @@ impl EncItConfig for EncItConfigImpl {
         self.friends.iter().find(|f| f.name == friend)
     }
 
-    fn friend_by_public_key_sha(&self, identity_public_key_sha: &str) -> Option<&EncItFriend> {
+    fn friend_by_key_id(&self, key_id: &str) -> Option<&EncItFriend> {
         self.friends.iter().find(|friend| {
-            let pub_key_sha = friend
-                .public_key
-                .sha_pem()
-                .unwrap_or_else(|_| panic!("cannot get public key for identity {}", friend.name));
-            pub_key_sha == identity_public_key_sha
+            // an unsupported key does not match, the other friends are still usable
+            let key_ids = match friend.public_key.key_ids() {
+                Ok(key_ids) => key_ids,
+                Err(e) => {
+                    warn!("cannot get the key ids of friend {}: {}", friend.name, e);
+                    return false;
+                }
+            };
+            key_ids.iter().any(|friend_key_id| friend_key_id == key_id)
         })
     }
 
@@ -261,13 +658,14 @@ impl EncItConfig for EncItConfigImpl {
         &self,
         friend_name: &str,
         public_key: &EncItPEM,
+        trust: EncItTrustLevel,
     ) -> Result<Box<dyn EncItConfig>, EncItError> {
         if self.friend(friend_name).is_some() {
             return Err(EncItError::FriendAlreadyExist());
         }
         let friend = EncItFriend {
-            name: friend_name.to_string(),
-            public_key: public_key.clone(),
+            trust,
+            ..EncItFriend::new(friend_name.to_string(), public_key.clone())
         };
         let mut new_friends: Vec<EncItFriend> = Vec::with_capacity(self.friends.len() + 1);
         new_friends.extend(self.friends.to_vec());
@@ -280,6 +678,39 @@ impl EncItConfig for EncItConfigImpl {
         }))
     }
 
+    fn set_friend_trust(
+        &self,
+        friend_name: &str,
+        trust: EncItTrustLevel,
+    ) -> Result<Box<dyn EncItConfig>, EncItError> {
+        if self.friend(friend_name).is_none() {
+            return Err(EncItError::FriendNotFound(friend_name.to_string()));
+        }
+        let verified_at = (trust == EncItTrustLevel::Verified)
+            .then(|| humantime::format_rfc3339_seconds(SystemTime::now()).to_string());
+        let new_friends = self
+            .friends
+            .iter()
+            .map(|friend| {
+                if friend.name == friend_name {
+                    EncItFriend {
+                        trust,
+                        verified_at: verified_at.clone(),
+                        ..friend.clone()
+                    }
+                } else {
+                    friend.clone()
+                }
+            })
+            .collect();
+
+        Ok(Box::new(EncItConfigImpl {
+            path: self.path.clone(),
+            identities: self.identities.to_vec(),
+            friends: new_friends,
+        }))
+    }
+
     fn add_identity<'a>(
         &self,
         identity_name: &'a str,
@@ -289,13 +720,13 @@ impl EncItConfig for EncItConfigImpl {
         if self.identity(identity_name).is_some() {
             return Err(EncItError::IdentityAlreadyExist());
         }
-        let identity = EncItIdentity {
-            name: identity_name.to_string(),
-            private_key: EncItPrivateKey {
+        let identity = EncItIdentity::new(
+            identity_name.to_string(),
+            EncItPrivateKey {
                 key: private_key.clone(),
                 password: passphrase.map(|p| p.to_string()),
             },
-        };
+        );
         let mut new_identities = Vec::with_capacity(self.identities.len() + 1);
         new_identities.extend(self.identities.to_vec());
         new_identities.extend(vec![identity]);
@@ -307,6 +738,68 @@ impl EncItConfig for EncItConfigImpl {
         }))
     }
 
+    fn rotate_identity(
+        &self,
+        identity_name: &str,
+        private_key: &EncItPEM,
+    ) -> Result<Box<dyn EncItConfig>, EncItError> {
+        if self.identity(identity_name).is_none() {
+            return Err(EncItError::IdentityNotFound(identity_name.to_string()));
+        }
+        let new_identities = self
+            .identities
+            .iter()
+            .map(|identity| {
+                if identity.name != identity_name {
+                    return identity.clone();
+                }
+                let mut previous_keys = vec![identity.private_key.clone()];
+                previous_keys.extend(identity.previous_keys.to_vec());
+                EncItIdentity {
+                    name: identity.name.clone(),
+                    private_key: EncItPrivateKey::new(
+                        private_key.clone(),
+                        identity.private_key.password.clone(),
+                    ),
+                    previous_keys,
+                }
+            })
+            .collect();
+        Ok(Box::new(EncItConfigImpl {
+            path: self.path.clone(),
+            identities: new_identities,
+            friends: self.friends.to_vec(),
+        }))
+    }
+
+    fn set_friend_key(
+        &self,
+        friend_name: &str,
+        public_key: &EncItPEM,
+    ) -> Result<Box<dyn EncItConfig>, EncItError> {
+        if self.friend(friend_name).is_none() {
+            return Err(EncItError::FriendNotFound(friend_name.to_string()));
+        }
+        let new_friends = self
+            .friends
+            .iter()
+            .map(|friend| {
+                if friend.name != friend_name {
+                    return friend.clone();
+                }
+                EncItFriend {
+                    public_key: public_key.clone(),
+                    ..friend.clone()
+                }
+            })
+            .collect();
+        Ok(Box::new(EncItConfigImpl {
+            path: self.path.clone(),
+            identities: self.identities.to_vec(),
+            friends: new_friends,
+        }))
+    }
+
     fn identities(&self) -> &Vec<EncItIdentity> {
         &self.identities
     }
@@ -317,6 +810,10 @@ impl EncItConfig for EncItConfigImpl {
         config_file.write_all(yaml.as_bytes())?;
         config_file.flush().map_err(|e| e.into())
     }
+
+    fn path(&self) -> &str {
+        &self.path
+    }
 }
 
 #[cfg(test)]
@@ -347,6 +844,8 @@ pub mod tests {
         let (_, cfg) = get_valid_config()?;
         assert_eq!(cfg.identities().len(), 1);
         assert_eq!(cfg.friends().len(), 1);
+        // the friends saved before the trust levels
+        assert_eq!(cfg.friends()[0].trust(), EncItTrustLevel::Unverified);
         Ok(())
     }
 
@@ -355,8 +854,11 @@ pub mod tests {
         let (_, cfg) = get_valid_config()?;
         let new_friend_pub_key_hex = hex::encode(Rsa::generate(2048)?.public_key_to_pem()?);
 
-        let new_cfg =
-            cfg.add_friend("new-friend", &EncItPEM::Hex(new_friend_pub_key_hex.clone()))?;
+        let new_cfg = cfg.add_friend(
+            "new-friend",
+            &EncItPEM::Hex(new_friend_pub_key_hex.clone()),
+            EncItTrustLevel::Unverified,
+        )?;
         let new_friend = new_cfg.friend("new-friend");
         assert!(new_friend.is_some());
         assert_eq!(
@@ -367,6 +869,27 @@ pub mod tests {
         Ok(())
     }
 
+    #[test]
+    fn set_friend_trust() -> Result<(), EncItError> {
+        let (_, cfg) = get_valid_config()?;
+        let verified_cfg = cfg.set_friend_trust("friend-1", EncItTrustLevel::Verified)?;
+        let friend = verified_cfg.friend("friend-1").unwrap();
+        assert_eq!(friend.trust(), EncItTrustLevel::Verified);
+        assert!(friend
+            .verified_at()
+            .is_some_and(|date| humantime::parse_rfc3339(date).is_ok()));
+
+        let revoked_cfg = verified_cfg.set_friend_trust("friend-1", EncItTrustLevel::Revoked)?;
+        let friend = revoked_cfg.friend("friend-1").unwrap();
+        assert_eq!(friend.trust(), EncItTrustLevel::Revoked);
+        assert!(friend.verified_at().is_none());
+        assert!(matches!(
+            cfg.set_friend_trust("non-existent-friend", EncItTrustLevel::Verified),
+            Err(EncItError::FriendNotFound(_))
+        ));
+        Ok(())
+    }
+
     #[test]
     fn add_identity() -> Result<(), EncItError> {
         let (_, cfg) = get_valid_config()?;
@@ -392,6 +915,60 @@ pub mod tests {
         Ok(())
     }
 
+    #[test]
+    fn rotate_identity() -> Result<(), EncItError> {
+        let (_, cfg) = get_valid_config()?;
+        let old_key_id = cfg
+            .identity("identity-1")
+            .unwrap()
+            .private_key()
+            .public_key_thumbprint()?;
+        // the passphrase of the current key protects the new one
+        let new_private_key = hex::encode(EncItKeyType::private_key_to_pem_passphrase(
+            &EncItKeyType::Ed25519.generate()?,
+            "test",
+        )?);
+        let new_cfg = cfg.rotate_identity("identity-1", &EncItPEM::Hex(new_private_key.clone()))?;
+        let identity = new_cfg.identity("identity-1").unwrap();
+        assert_eq!(identity.private_key.key.hex_pem()?, new_private_key);
+        assert_eq!(identity.private_key().password(), Some("test"));
+        assert_eq!(identity.private_key().key_type()?, EncItKeyType::Ed25519);
+        assert_eq!(identity.previous_keys().len(), 1);
+        assert_eq!(identity.previous_keys()[0].password(), Some("test"));
+        let new_key_id = identity.private_key().public_key_thumbprint()?;
+        // the messages for the old key are still decrypted by the same identity
+        assert_eq!(
+            new_cfg.identity_by_key_id(&old_key_id).map(|i| i.name()),
+            Some("identity-1")
+        );
+        assert_eq!(
+            new_cfg.identity_by_key_id(&new_key_id).map(|i| i.name()),
+            Some("identity-1")
+        );
+        assert!(matches!(
+            cfg.rotate_identity("non-existent-identity", &EncItPEM::Hex(new_private_key)),
+            Err(EncItError::IdentityNotFound(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn set_friend_key() -> Result<(), EncItError> {
+        let (_, cfg) = get_valid_config()?;
+        let new_public_key = hex::encode(Rsa::generate(2048)?.public_key_to_pem()?);
+        let new_cfg = cfg
+            .set_friend_trust("friend-1", EncItTrustLevel::Verified)?
+            .set_friend_key("friend-1", &EncItPEM::Hex(new_public_key.clone()))?;
+        let friend = new_cfg.friend("friend-1").unwrap();
+        assert_eq!(friend.public_key().hex_pem()?, new_public_key);
+        assert_eq!(friend.trust(), EncItTrustLevel::Verified);
+        assert!(matches!(
+            cfg.set_friend_key("non-existent-friend", &EncItPEM::Hex(new_public_key)),
+            Err(EncItError::FriendNotFound(_))
+        ));
+        Ok(())
+    }
+
     #[test]
     fn save() -> Result<(), EncItError> {
         let (mut cfg_file, cfg) = get_valid_config()?;
@@ -402,13 +979,17 @@ pub mod tests {
                 Cipher::aes_128_cbc(),
                 identity_passphrase.as_bytes(),
             )?);
-        cfg.add_friend("new-friend", &EncItPEM::Hex(new_friend_pub_key_hex))?
-            .add_identity(
-                "new-identity",
-                &EncItPEM::Hex(new_identity_private_key_hex),
-                Some(identity_passphrase),
-            )?
-            .save()?;
+        cfg.add_friend(
+            "new-friend",
+            &EncItPEM::Hex(new_friend_pub_key_hex),
+            EncItTrustLevel::Tofu,
+        )?
+        .add_identity(
+            "new-identity",
+            &EncItPEM::Hex(new_identity_private_key_hex),
+            Some(identity_passphrase),
+        )?
+        .save()?;
 
         cfg_file.rewind()?;
         let mut new_cfg_content = String::new();
@@ -421,6 +1002,54 @@ pub mod tests {
         Ok(())
     }
 
+    #[test]
+    fn key_type() -> Result<(), EncItError> {
+        for key_type_name in EncItKeyType::NAMES {
+            let key_type = EncItKeyType::from_name(key_type_name)?;
+            let key = key_type.generate()?;
+            let private_key = EncItPrivateKey::new(
+                EncItPEM::Hex(hex::encode(EncItKeyType::private_key_to_pem(&key)?)),
+                None,
+            );
+            assert_eq!(private_key.key_type()?, key_type);
+            let public_key = EncItPEM::Pem(String::from_utf8(private_key.public_key_pem()?)?);
+            assert_eq!(public_key.key_type()?, key_type);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn public_key_jwk() -> Result<(), EncItError> {
+        for key_type_name in EncItKeyType::NAMES {
+            let key = EncItKeyType::from_name(key_type_name)?.generate()?;
+            let private_key = EncItPrivateKey::new(
+                EncItPEM::Hex(hex::encode(EncItKeyType::private_key_to_pem(&key)?)),
+                None,
+            );
+            let public_key = EncItPEM::from_jwk(&private_key.public_key_jwk()?)?;
+            assert_eq!(public_key.sha_pem()?, private_key.public_key_pem_sha()?);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn ed25519_encryption_key() -> Result<(), EncItError> {
+        let key = EncItKeyType::Ed25519.generate()?;
+        let private_key = EncItPrivateKey::new(
+            EncItPEM::Hex(hex::encode(EncItKeyType::private_key_to_pem(&key)?)),
+            None,
+        );
+        let public_key = EncItPEM::Pem(String::from_utf8(private_key.public_key_pem()?)?);
+        let x25519_private_key = PKey::private_key_from_pem(&private_key.encryption_key_pem()?)?;
+        let x25519_public_key = PKey::public_key_from_pem(&public_key.encryption_key_pem()?)?;
+        assert_eq!(x25519_private_key.id(), Id::X25519);
+        assert_eq!(
+            x25519_private_key.raw_public_key()?,
+            x25519_public_key.raw_public_key()?
+        );
+        Ok(())
+    }
+
     #[test]
     fn identity_found() -> Result<(), EncItError> {
         let (_, cfg) = get_valid_config()?;
@@ -437,12 +1066,62 @@ pub mod tests {
     }
 
     #[test]
-    fn identity_by_public_key_sha_found() -> Result<(), EncItError> {
+    fn identity_by_key_id_found() -> Result<(), EncItError> {
+        let (_, cfg) = get_valid_config()?;
+        cfg.identity_by_key_id("23b59f9973066dbfd3c69a714055cfd87391938c685a3062580343e2e3f2d6e0")
+            .expect("identity-1 not found");
+        Ok(())
+    }
+
+    #[test]
+    fn friend_by_key_id_canonical() -> Result<(), EncItError> {
         let (_, cfg) = get_valid_config()?;
-        cfg.identity_by_public_key_sha(
-            "23b59f9973066dbfd3c69a714055cfd87391938c685a3062580343e2e3f2d6e0",
-        )
-        .expect("identity-1 not found");
+        let key = EncItKeyType::Rsa.generate()?;
+        let private_key = EncItPrivateKey::new(
+            EncItPEM::Hex(hex::encode(EncItKeyType::private_key_to_pem(&key)?)),
+            None,
+        );
+        // PKCS#1 with CRLF line endings and a trailing newline
+        let pkcs1_pem = String::from_utf8(key.rsa()?.public_key_to_pem_pkcs1()?)?;
+        let public_key = EncItPEM::Pem(format!("{}\n", pkcs1_pem.replace('\n', "\r\n")));
+        let cfg = cfg.add_friend("pkcs1-friend", &public_key, EncItTrustLevel::Unverified)?;
+        for key_id in [
+            private_key.public_key_thumbprint()?,
+            private_key.public_key_pem_sha()?,
+        ] {
+            assert_eq!(
+                cfg.friend_by_key_id(&key_id).map(|friend| friend.name()),
+                Some("pkcs1-friend")
+            );
+        }
+        assert_eq!(
+            public_key.fingerprint()?,
+            private_key.public_key_fingerprint()?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn friend_by_key_id_unsupported_key() -> Result<(), EncItError> {
+        let (_, cfg) = get_valid_config()?;
+        let p521 = EcKey::generate(&*EcGroup::from_curve_name(Nid::SECP521R1)?)?;
+        let p521_pem = EncItPEM::Pem(String::from_utf8(p521.public_key_to_pem()?)?);
+        let key = EncItKeyType::Ed25519.generate()?;
+        let private_key = EncItPrivateKey::new(
+            EncItPEM::Hex(hex::encode(EncItKeyType::private_key_to_pem(&key)?)),
+            None,
+        );
+        let public_key = EncItPEM::Pem(String::from_utf8(key.public_key_to_pem()?)?);
+        let cfg = cfg
+            .add_friend("p521-friend", &p521_pem, EncItTrustLevel::Unverified)?
+            .add_friend("valid-friend", &public_key, EncItTrustLevel::Unverified)?;
+        // the unsupported key is skipped, it does not break the lookup of the other friends
+        assert_eq!(
+            cfg.friend_by_key_id(&private_key.public_key_thumbprint()?)
+                .map(|friend| friend.name()),
+            Some("valid-friend")
+        );
+        assert!(cfg.friend_by_key_id("unknown-key-id").is_none());
         Ok(())
     }
 
@@ -462,12 +1141,10 @@ pub mod tests {
     }
 
     #[test]
-    fn friend_by_public_key_sha_found() -> Result<(), EncItError> {
+    fn friend_by_key_id_found() -> Result<(), EncItError> {
         let (_, cfg) = get_valid_config()?;
-        cfg.friend_by_public_key_sha(
-            "dbb90347dcf9f816ef522e94e69bf2964de87f966175e7e15d27c34ae0e9fbbc",
-        )
-        .expect("friend-1 not found");
+        cfg.friend_by_key_id("dbb90347dcf9f816ef522e94e69bf2964de87f966175e7e15d27c34ae0e9fbbc")
+            .expect("friend-1 not found");
         Ok(())
     }
 
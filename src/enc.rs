@@ -1,14 +1,27 @@
-use josekit::jwe::{JweHeader, RSA_OAEP};
-use josekit::jws::{JwsHeader, RS256};
+use josekit::jwe::{
+    JweContext, JweDecrypter, JweEncrypter, JweHeader, JweHeaderSet, ECDH_ES_A256KW,
+    PBES2_HS512_A256KW, RSA_OAEP,
+};
+use josekit::jwk::Jwk;
+use josekit::jws::{EdDSA, JwsHeader, JwsHeaderSet, JwsSigner, JwsVerifier, ES256, ES384, RS256};
 use josekit::jwt::JwtPayload;
-use josekit::{jwt, Value};
+use josekit::{jwe, jws, jwt, Map, Value};
 use log::debug;
-use serde::{Deserialize, Serialize};
+use openssl::rand::rand_bytes;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::cell::Cell;
+use std::convert::TryInto;
 use std::rc::Rc;
 use std::string::String;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use crate::config::{EncItConfig, EncItFriend, EncItIdentity};
+use crate::compression::{BoundedDeflate, EncItCompression, DEFLATE, MAX_DECOMPRESSED_LEN};
+use crate::config::{
+    EncItConfig, EncItFriend, EncItIdentity, EncItKeyType, EncItPEM, EncItPrivateKey,
+    EncItTrustLevel,
+};
 use crate::errors::EncItError;
+use crate::seen::EncItSeenMessages;
 
 #[cfg(test)]
 use mockall::{automock, predicate::*};
@@ -17,31 +30,249 @@ use mockall::{automock, predicate::*};
 pub trait EncIt {
     fn encrypt<'a>(
         &self,
-        identity: &'a str,
-        friend: &'a str,
-        subject: Option<&'a str>,
-        message: &'a str,
+        identity: Option<&'a str>,
+        friends: &'a [&'a str],
+        options: &'a EncItEncryptOptions,
+        message: &'a [u8],
     ) -> Result<String, EncItError>;
     fn decrypt<'a>(
         &self,
         jwe: &'a str,
         identity: Option<&'a str>,
+        options: &'a EncItDecryptOptions,
     ) -> Result<EncItMessage, EncItError>;
+    fn forward<'a>(
+        &self,
+        jwe: &'a str,
+        identity: &'a str,
+        friends: &'a [&'a str],
+        options: &'a EncItDecryptOptions,
+    ) -> Result<String, EncItError>;
+    fn sender_key<'a>(
+        &self,
+        jwe: &'a str,
+        identity: Option<&'a str>,
+    ) -> Result<EncItPEM, EncItError>;
+    fn sign<'a>(&self, identity: &'a str, message: &'a [u8]) -> Result<String, EncItError>;
+    fn verify<'a>(&self, signature: &'a str, message: &'a [u8]) -> Result<String, EncItError>;
+    fn clear_sign<'a>(&self, identity: &'a str, message: &'a str) -> Result<String, EncItError>;
+    fn transition<'a>(
+        &self,
+        identity: &'a str,
+        new_key: &'a EncItPrivateKey,
+    ) -> Result<String, EncItError>;
+    /// the friend whose current key signed the statement, and its new key
+    fn verify_transition(&self, statement: &str) -> Result<(String, EncItPEM), EncItError>;
+    /// records the id of a message decrypted with `defer_seen`
+    fn mark_seen(&self, message: &EncItMessage) -> Result<(), EncItError>;
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct EncItEncryptOptions {
+    pub subject: Option<String>,
+    /// the message is refused by the recipient after this duration
+    pub expires_in: Option<Duration>,
+    /// the recipient refuses to decrypt the message a second time
+    pub burn_after_reading: bool,
+    /// the sender public key is added to the signature header
+    pub embed_key: bool,
+    /// DEFLATE of the plaintext before the encryption (JWE zip header)
+    pub compression: EncItCompression,
+    /// the message is encrypted with a password instead of the friend keys
+    pub password: Option<EncItPassword>,
+    /// PBES2 iterations, PBES2_DEFAULT_ITERATIONS if not set
+    pub password_iterations: Option<u32>,
+    /// the recipients fingerprints (rcp) are not added to the JWE header
+    pub hide_recipients: bool,
+    /// the original file information, signed with the message
+    pub file: Option<EncItFileMetadata>,
+}
+
+/// original file information carried in the encrypted message, every field is optional
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq)]
+pub struct EncItFileMetadata {
+    /// file name without directories
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// MIME type
+    #[serde(rename = "contentType", skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// modification time, seconds since the unix epoch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mtime: Option<u64>,
+    /// unix permission bits
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct EncItDecryptOptions {
+    pub allow_expired: bool,
+    /// messages signed by a key not present in the friends list are not refused
+    pub allow_unknown_sender: bool,
+    /// required by the messages encrypted with a password
+    pub password: Option<EncItPassword>,
+    /// the message id is recorded by `mark_seen` once the whole message is authenticated
+    pub defer_seen: bool,
+}
+
+pub const PBES2_DEFAULT_ITERATIONS: u32 = 210_000;
+pub const PBES2_MIN_ITERATIONS: u32 = 1000;
+/// a hostile message cannot make the key derivation last forever
+const PBES2_MAX_ITERATIONS: u64 = 10_000_000;
+const PBES2_ALGORITHM_PREFIX: &str = "PBES2";
+
+#[derive(Clone, PartialEq)]
+pub struct EncItPassword(String);
+
+impl EncItPassword {
+    pub fn new(password: String) -> Self {
+        EncItPassword(password)
+    }
+}
+
+/// the password is not printed in the debug logs
+impl std::fmt::Debug for EncItPassword {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("EncItPassword(***)")
+    }
+}
+
+/// the message key is derived from a password (PBES2), only the protected header is read
+pub fn is_password_encrypted(message: &str) -> bool {
+    let header = message.trim_start().split('.').next().unwrap_or_default();
+    base64::decode_config(header, base64::URL_SAFE_NO_PAD)
+        .ok()
+        .and_then(|header| serde_json::from_slice::<Map<String, Value>>(&header).ok())
+        .and_then(|header| {
+            header
+                .get("alg")
+                .and_then(|alg| alg.as_str())
+                .map(|alg| alg.to_string())
+        })
+        .is_some_and(|alg| alg.starts_with(PBES2_ALGORITHM_PREFIX))
+}
+
+const BURN_AFTER_READING_CLAIM: &str = "burn";
+const FILE_CLAIM: &str = "file";
+/// claims copied in the protected header of the message signature
+const MESSAGE_CLAIMS: [&str; 7] = [
+    "jti",
+    "iat",
+    "exp",
+    "aud",
+    "sub",
+    BURN_AFTER_READING_CLAIM,
+    FILE_CLAIM,
+];
+/// signed audience of the password encrypted messages, they have no recipient key
+const PASSWORD_AUDIENCE: &str = "password";
+
+/// JWE content type of the binary plaintext: the big endian length of the JSON envelope,
+/// the envelope and the raw message bytes. Without it the plaintext is the legacy JWT
+/// whose claim contains the base64 of the message
+const BINARY_PAYLOAD_CONTENT_TYPE: &str = "encit-binary";
+const ENVELOPE_LEN_SIZE: usize = 4;
+
+/// JSON part of the binary plaintext: the detached signature of the message bytes,
+/// or the bare claims of an anonymous message
+#[derive(Debug, Serialize, Deserialize)]
+struct EncItEnvelope {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    jws: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    claims: Option<Map<String, Value>>,
+    /// detached signature of the message by the friend that forwarded it to the recipients
+    #[serde(rename = "fwd", skip_serializing_if = "Option::is_none")]
+    forwarded: Option<String>,
+}
+
+const CLEAR_SIGNED_BEGIN: &str = "-----BEGIN ENCIT SIGNED MESSAGE-----";
+const CLEAR_SIGNATURE_BEGIN: &str = "-----BEGIN ENCIT SIGNATURE-----";
+const CLEAR_SIGNATURE_END: &str = "-----END ENCIT SIGNATURE-----";
+const CLEAR_SIGNATURE_LINE_LEN: usize = 64;
+
+/// JWS type of the transition statement
+const TRANSITION_TYPE: &str = "encit-transition";
+
+/// key rotation announced to the friends, the statement (JWS JSON serialization)
+/// is signed by both the old and the new identity keys
+#[derive(Debug, Serialize, Deserialize)]
+struct EncItTransition {
+    /// identity name, informative only
+    #[serde(rename = "sub")]
+    identity: String,
+    #[serde(rename = "oldKid")]
+    old_key_id: String,
+    #[serde(rename = "newKid")]
+    new_key_id: String,
+    /// base64 of the new public key PEM
+    #[serde(rename = "newKey")]
+    new_key: String,
+    #[serde(rename = "iat")]
+    issued_at: u64,
+}
+
+/// a clear-signed message can be surrounded by other text (e.g. an email body)
+pub fn is_clear_signed(message: &str) -> bool {
+    message.contains(CLEAR_SIGNED_BEGIN)
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct EncItMessage {
-    sender: String,
+    sender: Option<String>,
+    #[serde(rename = "senderFingerprint")]
+    sender_fingerprint: Option<String>,
+    /// trust level of the sender friend key
+    #[serde(rename = "senderTrust", skip_serializing_if = "Option::is_none")]
+    sender_trust: Option<EncItTrustLevel>,
     receiver: String,
     subject: Option<String>,
-    payload: String,
+    /// base64 encoded in the JSON output
+    #[serde(
+        serialize_with = "serialize_base64",
+        deserialize_with = "deserialize_base64"
+    )]
+    payload: Vec<u8>,
+    /// the signature has been checked, with the sender embedded key for the unknown senders
+    signed: bool,
     verified: bool,
+    #[serde(rename = "issuedAt")]
+    issued_at: Option<u64>,
+    #[serde(rename = "expiresAt")]
+    expires_at: Option<u64>,
+    id: Option<String>,
+    #[serde(rename = "burnAfterReading")]
+    burn_after_reading: bool,
+    /// the message was already decrypted before
+    replayed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file: Option<EncItFileMetadata>,
+    /// the friend that forwarded the message keeping the sender signature
+    #[serde(rename = "forwardedBy", skip_serializing_if = "Option::is_none")]
+    forwarded_by: Option<String>,
+    #[serde(
+        rename = "forwarderFingerprint",
+        skip_serializing_if = "Option::is_none"
+    )]
+    forwarder_fingerprint: Option<String>,
 }
 
 #[allow(dead_code)]
 impl EncItMessage {
-    pub fn sender(&self) -> &str {
-        &self.sender
+    /// the sender friend name, empty for unsigned (anonymous) messages
+    pub fn sender(&self) -> Option<&str> {
+        self.sender.as_deref()
+    }
+    /// sha of the sender public key, present also for unknown senders
+    pub fn sender_fingerprint(&self) -> Option<&str> {
+        self.sender_fingerprint.as_deref()
+    }
+    /// the trust level of the sender, only for the senders in the friends list
+    pub fn sender_trust(&self) -> Option<EncItTrustLevel> {
+        self.sender_trust
     }
     pub fn receiver(&self) -> &str {
         &self.receiver
@@ -49,124 +280,974 @@ impl EncItMessage {
     pub fn subject(&self) -> &Option<String> {
         &self.subject
     }
-    pub fn payload(&self) -> &str {
+    pub fn payload(&self) -> &[u8] {
         &self.payload
     }
+    pub fn signed(&self) -> bool {
+        self.signed
+    }
     pub fn verified(&self) -> bool {
         self.verified
     }
+    /// seconds since the unix epoch
+    pub fn issued_at(&self) -> Option<u64> {
+        self.issued_at
+    }
+    /// seconds since the unix epoch
+    pub fn expires_at(&self) -> Option<u64> {
+        self.expires_at
+    }
+    pub fn id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+    pub fn burn_after_reading(&self) -> bool {
+        self.burn_after_reading
+    }
+    pub fn replayed(&self) -> bool {
+        self.replayed
+    }
+    /// the original file information, if added by the sender
+    pub fn file(&self) -> Option<&EncItFileMetadata> {
+        self.file.as_ref()
+    }
+    /// the forwarder friend name, None for unknown forwarders
+    pub fn forwarded_by(&self) -> Option<&str> {
+        self.forwarded_by.as_deref()
+    }
+    /// thumbprint of the forwarder public key, present only for forwarded messages
+    pub fn forwarder_fingerprint(&self) -> Option<&str> {
+        self.forwarder_fingerprint.as_deref()
+    }
 
     #[cfg(test)]
     pub fn new(
-        sender: String,
+        sender: Option<String>,
         receiver: String,
         subject: Option<String>,
-        payload: String,
+        payload: Vec<u8>,
         verified: bool,
     ) -> Self {
         EncItMessage {
+            signed: sender.is_some(),
             sender,
+            sender_fingerprint: None,
+            sender_trust: None,
             receiver,
             subject,
             payload,
             verified,
+            issued_at: None,
+            expires_at: None,
+            id: None,
+            burn_after_reading: false,
+            replayed: false,
+            file: None,
+            forwarded_by: None,
+            forwarder_fingerprint: None,
         }
     }
+
+    #[cfg(test)]
+    pub fn with_file(mut self, file: EncItFileMetadata) -> Self {
+        self.file = Some(file);
+        self
+    }
 }
 
 pub struct EncItImpl {
     config: Rc<dyn EncItConfig>,
+    seen_messages: Option<Rc<dyn EncItSeenMessages>>,
 }
 
 impl EncIt for EncItImpl {
     fn encrypt(
         &self,
+        identity: Option<&str>,
+        friends: &[&str],
+        options: &EncItEncryptOptions,
+        message: &[u8],
+    ) -> Result<String, EncItError> {
+        let identity = identity
+            .map(|identity| {
+                self.config
+                    .identity(identity)
+                    .ok_or_else(|| EncItError::IdentityNotFound(identity.to_string()))
+            })
+            .transpose()?;
+        if friends.is_empty() && options.password.is_none() {
+            return Err(EncItError::InvalidCommand(
+                "at least one friend is required".to_string(),
+            ));
+        }
+        if !friends.is_empty() && options.password.is_some() {
+            return Err(EncItError::InvalidCommand(
+                "a message is encrypted either for friends or with a password".to_string(),
+            ));
+        }
+        let friends = self.recipient_friends(friends)?;
+
+        let mut claims = Self::create_claims(options)?;
+        // the recipients and the subject are signed with the content, so that a recipient
+        // cannot re-encrypt the signed message to someone else
+        let audience = match options.password {
+            Some(_) => vec![PASSWORD_AUDIENCE.to_string()],
+            None => friends
+                .iter()
+                .map(|friend| friend.public_key().thumbprint())
+                .collect::<Result<Vec<String>, EncItError>>()?,
+        };
+        claims.set_audience(audience);
+        if let Some(subject) = &options.subject {
+            claims.set_subject(subject);
+        }
+        let envelope = if let Some(identity) = identity {
+            let jws = Self::create_detached_jws(identity, &claims, options.embed_key, message)?;
+            debug!("jws:{}", &jws);
+            EncItEnvelope {
+                jws: Some(jws),
+                claims: None,
+                forwarded: None,
+            }
+        } else {
+            // anonymous: no signature and no issuer
+            EncItEnvelope {
+                jws: None,
+                claims: Some(claims.claims_set().clone()),
+                forwarded: None,
+            }
+        };
+        let payload = Self::create_binary_payload(&envelope, message)?;
+        let compress = options.compression.compress(message)?;
+        let jwe = match &options.password {
+            Some(password) => Self::create_password_jwe(
+                &payload,
+                password,
+                options
+                    .password_iterations
+                    .unwrap_or(PBES2_DEFAULT_ITERATIONS),
+                compress,
+            )?,
+            None => Self::create_jwe(&payload, &friends, compress, options.hide_recipients)?,
+        };
+        debug!("jwe:{}", &jwe);
+        Ok(jwe)
+    }
+
+    fn decrypt(
+        &self,
+        jwe: &str,
+        identity: Option<&str>,
+        options: &EncItDecryptOptions,
+    ) -> Result<EncItMessage, EncItError> {
+        if is_clear_signed(jwe) {
+            return self.verify_clear_signed(jwe, options);
+        }
+        let (payload, header, receiver, audience) =
+            self.extract_message_jwe(jwe.trim(), identity, options)?;
+        if header.content_type() != Some(BINARY_PAYLOAD_CONTENT_TYPE) {
+            // legacy messages carry the subject in the clear
+            return self.decrypt_legacy(
+                Self::legacy_payload(&payload)?,
+                receiver,
+                header.subject().map(|s| s.to_string()),
+                options,
+            );
+        }
+        self.decrypt_binary_payload(&payload, receiver, &audience, options)
+    }
+
+    /// the signature of the original sender is kept, the forwarder signs the message for the
+    /// new recipients
+    fn forward(
+        &self,
+        jwe: &str,
         identity: &str,
-        friend: &str,
-        subject: Option<&str>,
-        message: &str,
+        friends: &[&str],
+        options: &EncItDecryptOptions,
     ) -> Result<String, EncItError> {
-        let identity = self
+        let forwarder = self
             .config
             .identity(identity)
             .ok_or_else(|| EncItError::IdentityNotFound(identity.to_string()))?;
-        let friend = self
-            .config
-            .friend(friend)
-            .ok_or_else(|| EncItError::FriendNotFound(friend.to_string()))?;
-
-        let jws = Self::create_jws(message, identity)?;
-        debug!("jws:{}", &jws);
-        let jwe = Self::create_jwe(subject, &jws, identity, friend)?;
-        debug!("jwe:{}", &jwe);
+        if friends.is_empty() {
+            return Err(EncItError::InvalidCommand(
+                "at least one friend is required".to_string(),
+            ));
+        }
+        let friends = self.recipient_friends(friends)?;
+        let (payload, header, receiver, audience) =
+            self.extract_message_jwe(jwe.trim(), Some(identity), options)?;
+        if header.content_type() != Some(BINARY_PAYLOAD_CONTENT_TYPE) {
+            return Err(EncItError::InvalidCommand(
+                "legacy messages cannot be forwarded, decrypt and encrypt them again".to_string(),
+            ));
+        }
+        // only valid messages are forwarded
+        let original = self.decrypt_binary_payload(&payload, receiver, &audience, options)?;
+        if original.burn_after_reading() {
+            return Err(EncItError::InvalidCommand(
+                "burn after reading messages cannot be forwarded".to_string(),
+            ));
+        }
+        let (envelope, message) = Self::split_binary_payload(&payload)?;
+        let mut claims = Self::create_claims(&EncItEncryptOptions::default())?;
+        claims.set_audience(
+            friends
+                .iter()
+                .map(|friend| friend.public_key().thumbprint())
+                .collect::<Result<Vec<String>, EncItError>>()?,
+        );
+        let envelope = EncItEnvelope {
+            // the key lets a recipient that does not know the forwarder check its signature
+            forwarded: Some(Self::create_detached_jws(
+                forwarder, &claims, true, message,
+            )?),
+            ..envelope
+        };
+        let payload = Self::create_binary_payload(&envelope, message)?;
+        let jwe = Self::create_jwe(&payload, &friends, header.compression().is_some(), false)?;
+        debug!("forwarded jwe:{}", &jwe);
         Ok(jwe)
     }
 
-    fn decrypt(&self, jwe: &str, identity: Option<&str>) -> Result<EncItMessage, EncItError> {
-        let identity = if let Some(identity_name) = identity {
-            self.config.identity(identity_name)
-        } else {
-            let header = jwt::decode_header(jwe)?;
-            let receiver_public_key_sha = header.claim("rcp").unwrap().as_str().unwrap();
-            debug!("get identity by sha:{}", receiver_public_key_sha);
-            self.config
-                .identity_by_public_key_sha(receiver_public_key_sha)
+    /// the sender public key embedded in the message, verified against the message signature
+    fn sender_key(&self, jwe: &str, identity: Option<&str>) -> Result<EncItPEM, EncItError> {
+        let jwe = jwe.trim();
+        let (_, payload, header) = self.extract_receiver_jwe(jwe, identity)?;
+        let not_signed = || EncItError::DecodeError("the message is not signed".to_string());
+        let no_sender_key =
+            || EncItError::DecodeError("the message does not contain the sender key".to_string());
+        if header.content_type() != Some(BINARY_PAYLOAD_CONTENT_TYPE) {
+            let payload = Self::legacy_payload(&payload)?;
+            let sender_fingerprint = payload.issuer().ok_or_else(not_signed)?;
+            let jws = payload
+                .claim("message")
+                .and_then(|m| m.as_str())
+                .ok_or_else(EncItError::EmptyMessage)?;
+            let sender_key = Self::embedded_sender_key(
+                jwt::decode_header(jws)?.claim("jwk"),
+                sender_fingerprint,
+            )?
+            .ok_or_else(no_sender_key)?;
+            Self::extract_jws(
+                Some(jws),
+                &EncItFriend::new(String::new(), sender_key.clone()),
+            )?;
+            return Ok(sender_key);
         }
-        .ok_or_else(|| EncItError::IdentityNotFound(String::new()))?;
-        debug!("Identity found:{}", identity.name());
+        let (envelope, message) = Self::split_binary_payload(&payload)?;
+        let jws = envelope.jws.ok_or_else(not_signed)?;
+        let (header_b64, jws_header, signature) = Self::parse_detached_jws(&jws)?;
+        let sender_key = Self::embedded_sender_key(
+            jws_header.claim("jwk"),
+            Self::signature_key_id(&jws_header)?,
+        )?
+        .ok_or_else(no_sender_key)?;
+        Self::verify_detached_jws(header_b64, &jws_header, &signature, message, &sender_key)?;
+        Ok(sender_key)
+    }
 
-        let (payload, header) = Self::extract_jwe(jwe, identity)?;
+    /// detached JWS with unencoded payload (RFC 7797): header..signature
+    fn sign(&self, identity: &str, message: &[u8]) -> Result<String, EncItError> {
+        let identity = self
+            .config
+            .identity(identity)
+            .ok_or_else(|| EncItError::IdentityNotFound(identity.to_string()))?;
+        Self::create_detached_jws(identity, &JwtPayload::new(), false, message)
+    }
 
-        let friend = payload
-            .issuer()
-            .and_then(|friend_pub_key_sha| self.config.friend_by_public_key_sha(friend_pub_key_sha))
+    fn verify(&self, signature: &str, message: &[u8]) -> Result<String, EncItError> {
+        let (header_b64, jws_header, signature) = Self::parse_detached_jws(signature)?;
+        let friend = jws_header
+            .key_id()
+            .and_then(|friend_pub_key_sha| self.config.friend_by_key_id(friend_pub_key_sha))
             .ok_or_else(|| {
                 EncItError::FriendNotFound(
-                    "cannot find a friend that match with the message public key".to_string(),
+                    "cannot find a friend that match with the signature public key".to_string(),
                 )
             })?;
+        Self::check_not_revoked(friend)?;
+        Self::verify_detached_jws(
+            header_b64,
+            &jws_header,
+            &signature,
+            message,
+            friend.public_key(),
+        )?;
+        Ok(friend.name().to_string())
+    }
+
+    /// readable plaintext followed by the attached JWS of the same content
+    fn clear_sign(&self, identity: &str, message: &str) -> Result<String, EncItError> {
+        let identity = self
+            .config
+            .identity(identity)
+            .ok_or_else(|| EncItError::IdentityNotFound(identity.to_string()))?;
+        let content =
+            Self::create_content(&base64::encode(message), &EncItEncryptOptions::default())?;
+        let jws = Self::create_jws(&content, identity, false)?;
+        let jws_lines: Vec<&str> = jws
+            .as_bytes()
+            .chunks(CLEAR_SIGNATURE_LINE_LEN)
+            .map(|line| std::str::from_utf8(line).unwrap())
+            .collect();
+        Ok(format!(
+            "{}\n{}\n{}\n{}\n{}\n",
+            CLEAR_SIGNED_BEGIN,
+            message,
+            CLEAR_SIGNATURE_BEGIN,
+            jws_lines.join("\n"),
+            CLEAR_SIGNATURE_END
+        ))
+    }
 
-        let (verified, message) =
-            Self::extract_jws(payload.claim("message").unwrap().as_str(), friend)?;
+    fn transition(&self, identity: &str, new_key: &EncItPrivateKey) -> Result<String, EncItError> {
+        let identity = self
+            .config
+            .identity(identity)
+            .ok_or_else(|| EncItError::IdentityNotFound(identity.to_string()))?;
+        let old_key = identity.private_key();
+        let transition = EncItTransition {
+            identity: identity.name().to_string(),
+            old_key_id: old_key.public_key_thumbprint()?,
+            new_key_id: new_key.public_key_thumbprint()?,
+            new_key: base64::encode(new_key.public_key_pem()?),
+            issued_at: unix_time(SystemTime::now()),
+        };
+        let old_signer = Self::signer(old_key)?;
+        let new_signer = Self::signer(new_key)?;
+        jws::serialize_general_json(
+            &serde_json::to_vec(&transition)?,
+            &[
+                (
+                    &Self::transition_header(&transition.old_key_id),
+                    &*old_signer,
+                ),
+                (
+                    &Self::transition_header(&transition.new_key_id),
+                    &*new_signer,
+                ),
+            ],
+        )
+        .map_err(|e| e.into())
+    }
 
-        Ok(EncItMessage {
-            sender: friend.name().to_string(),
-            receiver: identity.name().to_string(),
-            subject: header.subject().map(|s| s.to_string()),
-            payload: message,
-            verified,
-        })
+    fn verify_transition(&self, statement: &str) -> Result<(String, EncItPEM), EncItError> {
+        let transition = Self::parse_transition(statement)?;
+        let friend = self
+            .config
+            .friend_by_key_id(&transition.old_key_id)
+            .ok_or_else(|| {
+                EncItError::FriendNotFound(
+                    "cannot find a friend that match with the transition old key".to_string(),
+                )
+            })?;
+        // the revoked key may be in the hands of someone else
+        Self::check_not_revoked(friend)?;
+        let new_key = EncItPEM::Base64(transition.new_key.clone());
+        if new_key.thumbprint()? != transition.new_key_id {
+            return Err(EncItError::InvalidSignature());
+        }
+        for (key_id, public_key) in [
+            (&transition.old_key_id, friend.public_key()),
+            (&transition.new_key_id, &new_key),
+        ] {
+            let verifier = Self::verifier(public_key)?;
+            let (_, header) = jws::deserialize_json_with_selector(statement, |header| {
+                Ok((header.key_id() == Some(key_id.as_str())).then_some(&*verifier))
+            })
+            .map_err(|_| EncItError::InvalidSignature())?;
+            if header.token_type() != Some(TRANSITION_TYPE) {
+                return Err(EncItError::InvalidSignature());
+            }
+        }
+        Ok((friend.name().to_string(), new_key))
+    }
+
+    fn mark_seen(&self, message: &EncItMessage) -> Result<(), EncItError> {
+        if let (Some(seen_messages), Some(id)) = (&self.seen_messages, message.id()) {
+            if !seen_messages.seen(id)? {
+                seen_messages.add(id)?;
+            }
+        }
+        Ok(())
     }
 }
 
 impl EncItImpl {
     pub fn new(config: Rc<dyn EncItConfig>) -> Self {
-        EncItImpl { config }
+        EncItImpl {
+            config,
+            seen_messages: None,
+        }
     }
 
-    fn create_jwe(
-        subject: Option<&str>,
+    /// decrypted message ids are recorded to detect replays
+    pub fn with_seen_messages(self, seen_messages: Rc<dyn EncItSeenMessages>) -> Self {
+        EncItImpl {
+            seen_messages: Some(seen_messages),
+            ..self
+        }
+    }
+
+    /// the message claims, signed by the sender unless the message is anonymous
+    fn create_claims(options: &EncItEncryptOptions) -> Result<JwtPayload, EncItError> {
+        let now = SystemTime::now();
+        let mut jti = [0u8; 16];
+        rand_bytes(&mut jti)?;
+        let mut claims = JwtPayload::new();
+        claims.set_jwt_id(hex::encode(jti));
+        claims.set_issued_at(&now);
+        if let Some(expires_in) = options.expires_in {
+            claims.set_expires_at(&(now + expires_in));
+        }
+        if options.burn_after_reading {
+            claims.set_claim(BURN_AFTER_READING_CLAIM, Some(Value::Bool(true)))?;
+        }
+        if let Some(file) = &options.file {
+            claims.set_claim(FILE_CLAIM, Some(serde_json::to_value(file)?))?;
+        }
+        Ok(claims)
+    }
+
+    /// the message claims with the message text, used by the clear-signed messages
+    fn create_content(
         message: &str,
-        identity: &EncItIdentity,
-        friend: &EncItFriend,
+        options: &EncItEncryptOptions,
+    ) -> Result<JwtPayload, EncItError> {
+        let mut content = Self::create_claims(options)?;
+        content.set_claim("message", Some(Value::String(message.to_string())))?;
+        Ok(content)
+    }
+
+    /// the message claims without sender information
+    fn message_from_claims(
+        claims: &JwtPayload,
+        payload: Vec<u8>,
+        receiver: String,
+        subject: Option<String>,
+        options: &EncItDecryptOptions,
+    ) -> Result<EncItMessage, EncItError> {
+        if let Some(expires_at) = claims.expires_at() {
+            if expires_at <= SystemTime::now() && !options.allow_expired {
+                return Err(EncItError::MessageExpired(
+                    humantime::format_rfc3339_seconds(expires_at).to_string(),
+                ));
+            }
+        }
+        Ok(EncItMessage {
+            sender: None,
+            sender_fingerprint: None,
+            sender_trust: None,
+            receiver,
+            subject,
+            payload,
+            signed: false,
+            verified: false,
+            issued_at: claims.issued_at().map(unix_time),
+            expires_at: claims.expires_at().map(unix_time),
+            id: claims.jwt_id().map(|id| id.to_string()),
+            burn_after_reading: claims.claim(BURN_AFTER_READING_CLAIM) == Some(&Value::Bool(true)),
+            replayed: false,
+            file: claims
+                .claim(FILE_CLAIM)
+                .map(|file| serde_json::from_value(file.clone()))
+                .transpose()?,
+            forwarded_by: None,
+            forwarder_fingerprint: None,
+        })
+    }
+
+    /// the legacy message claims, the message is a claim of the content
+    fn message_from_content(
+        content: JwtPayload,
+        receiver: String,
+        subject: Option<String>,
+        options: &EncItDecryptOptions,
+    ) -> Result<EncItMessage, EncItError> {
+        let message = content
+            .claim("message")
+            .and_then(|message| message.as_str())
+            .ok_or_else(EncItError::EmptyMessage)?;
+        // the stream headers were not base64 encoded, a JSON object is never valid base64
+        let payload = base64::decode(message).unwrap_or_else(|_| message.as_bytes().to_vec());
+        Self::message_from_claims(&content, payload, receiver, subject, options)
+    }
+
+    /// messages encrypted before the binary plaintext: a JWT whose message claim is
+    /// the signed content
+    fn decrypt_legacy(
+        &self,
+        payload: JwtPayload,
+        receiver: String,
+        subject: Option<String>,
+        options: &EncItDecryptOptions,
+    ) -> Result<EncItMessage, EncItError> {
+        let sender_fingerprint = match payload.issuer() {
+            Some(sender_fingerprint) => sender_fingerprint,
+            None => {
+                // anonymous message: the content is not signed
+                return self.check_replay(
+                    Self::message_from_content(payload, receiver, subject, options)?,
+                    options,
+                );
+            }
+        };
+        let jws = payload.claim("message").and_then(|m| m.as_str());
+
+        let message = match self.config.friend_by_key_id(sender_fingerprint) {
+            Some(friend) => {
+                let content = Self::extract_jws(jws, friend)?;
+                EncItMessage {
+                    sender: Some(friend.name().to_string()),
+                    sender_trust: Some(friend.trust()),
+                    signed: true,
+                    // a revoked key may be in the hands of someone else
+                    verified: friend.trust() != EncItTrustLevel::Revoked,
+                    ..Self::message_from_content(content, receiver, subject, options)?
+                }
+            }
+            None if options.allow_unknown_sender => {
+                let (content, signed) = Self::extract_unknown_sender_jws(jws, sender_fingerprint)?;
+                EncItMessage {
+                    signed,
+                    ..Self::message_from_content(content, receiver, subject, options)?
+                }
+            }
+            None => {
+                return Err(EncItError::FriendNotFound(
+                    "cannot find a friend that match with the message public key".to_string(),
+                ))
+            }
+        };
+        self.check_replay(
+            EncItMessage {
+                sender_fingerprint: Some(sender_fingerprint.to_string()),
+                ..message
+            },
+            options,
+        )
+    }
+
+    /// a revoked friend key is neither used to encrypt nor trusted to sign
+    fn check_not_revoked(friend: &EncItFriend) -> Result<(), EncItError> {
+        if friend.trust() == EncItTrustLevel::Revoked {
+            return Err(EncItError::FriendRevoked(friend.name().to_string()));
+        }
+        Ok(())
+    }
+
+    /// the revoked friend keys are never used to encrypt
+    fn recipient_friends(&self, friends: &[&str]) -> Result<Vec<&EncItFriend>, EncItError> {
+        friends
+            .iter()
+            .map(|friend| {
+                let friend = self
+                    .config
+                    .friend(friend)
+                    .ok_or_else(|| EncItError::FriendNotFound(friend.to_string()))?;
+                Self::check_not_revoked(friend)?;
+                Ok(friend)
+            })
+            .collect()
+    }
+
+    /// decrypts the message with the password or with the recipient identity, the audience is
+    /// the value that has to be signed by the sender
+    fn extract_message_jwe(
+        &self,
+        jwe: &str,
+        identity: Option<&str>,
+        options: &EncItDecryptOptions,
+    ) -> Result<(Vec<u8>, JweHeader, String, Vec<String>), EncItError> {
+        if is_password_encrypted(jwe) {
+            let password = options.password.as_ref().ok_or_else(|| {
+                EncItError::InvalidCommand("the message is encrypted with a password".to_string())
+            })?;
+            // there is no receiver identity
+            let (payload, header) = Self::extract_password_jwe(jwe, password)?;
+            Ok((
+                payload,
+                header,
+                String::new(),
+                vec![PASSWORD_AUDIENCE.to_string()],
+            ))
+        } else {
+            let (identity, payload, header) = self.extract_receiver_jwe(jwe, identity)?;
+            // older versions signed the sha of the public key PEM, the previous keys
+            // of a rotated identity are still valid recipients
+            let audience = identity.key_ids()?;
+            Ok((payload, header, identity.name().to_string(), audience))
+        }
+    }
+
+    /// the envelope followed by the message bytes
+    fn decrypt_binary_payload(
+        &self,
+        payload: &[u8],
+        receiver: String,
+        audience: &[String],
+        options: &EncItDecryptOptions,
+    ) -> Result<EncItMessage, EncItError> {
+        let (envelope, message) = Self::split_binary_payload(payload)?;
+        let jws = match envelope.jws {
+            Some(jws) => jws,
+            None => {
+                // anonymous message: the content is not signed
+                let claims = JwtPayload::from_map(envelope.claims.unwrap_or_default())?;
+                let subject = claims.subject().map(|s| s.to_string());
+                let forwarder = self.check_forwarder(
+                    envelope.forwarded.as_deref(),
+                    message,
+                    audience,
+                    options,
+                )?;
+                return self.check_replay(
+                    Self::forwarded_message(
+                        Self::message_from_claims(
+                            &claims,
+                            message.to_vec(),
+                            receiver,
+                            subject,
+                            options,
+                        )?,
+                        forwarder,
+                    ),
+                    options,
+                );
+            }
+        };
+        let (header_b64, jws_header, signature) = Self::parse_detached_jws(&jws)?;
+        let sender_fingerprint = Self::signature_key_id(&jws_header)?;
+        let claims = Self::header_claims(&jws_header)?;
+        // a forwarded message is signed for the new recipients by the forwarder
+        let forwarder =
+            self.check_forwarder(envelope.forwarded.as_deref(), message, audience, options)?;
+        if forwarder.is_none() {
+            Self::check_signed_recipient(&claims, audience)?;
+        }
+        let unknown_forwarder = matches!(forwarder, Some((None, _)));
+        let subject = claims.subject().map(|s| s.to_string());
+
+        let decrypted = match self.config.friend_by_key_id(sender_fingerprint) {
+            Some(friend) => {
+                Self::verify_detached_jws(
+                    header_b64,
+                    &jws_header,
+                    &signature,
+                    message,
+                    friend.public_key(),
+                )?;
+                EncItMessage {
+                    sender: Some(friend.name().to_string()),
+                    sender_trust: Some(friend.trust()),
+                    signed: true,
+                    // a revoked key may be in the hands of someone else
+                    verified: friend.trust() != EncItTrustLevel::Revoked,
+                    ..Self::message_from_claims(
+                        &claims,
+                        message.to_vec(),
+                        receiver,
+                        subject,
+                        options,
+                    )?
+                }
+            }
+            None if options.allow_unknown_sender => {
+                let sender_key =
+                    Self::embedded_sender_key(jws_header.claim("jwk"), sender_fingerprint)?;
+                if let Some(sender_key) = &sender_key {
+                    Self::verify_detached_jws(
+                        header_b64,
+                        &jws_header,
+                        &signature,
+                        message,
+                        sender_key,
+                    )?;
+                }
+                EncItMessage {
+                    signed: sender_key.is_some(),
+                    ..Self::message_from_claims(
+                        &claims,
+                        message.to_vec(),
+                        receiver,
+                        subject,
+                        options,
+                    )?
+                }
+            }
+            None => {
+                return Err(EncItError::FriendNotFound(
+                    "cannot find a friend that match with the message public key".to_string(),
+                ))
+            }
+        };
+        self.check_replay(
+            Self::forwarded_message(
+                EncItMessage {
+                    sender_fingerprint: Some(sender_fingerprint.to_string()),
+                    verified: decrypted.verified && !unknown_forwarder,
+                    ..decrypted
+                },
+                forwarder,
+            ),
+            options,
+        )
+    }
+
+    /// the forwarder name and fingerprint, the forwarder signature has to be valid and
+    /// signed for the recipient. The signature of an unknown forwarder is verified with
+    /// the embedded key: the original recipient check is skipped only for a verified forward,
+    /// and the message forwarded by an unknown forwarder is not verified
+    fn check_forwarder(
+        &self,
+        forwarded: Option<&str>,
+        message: &[u8],
+        audience: &[String],
+        options: &EncItDecryptOptions,
+    ) -> Result<Option<(Option<String>, String)>, EncItError> {
+        let forwarded = match forwarded {
+            Some(forwarded) => forwarded,
+            None => return Ok(None),
+        };
+        let (header_b64, jws_header, signature) = Self::parse_detached_jws(forwarded)?;
+        let forwarder_fingerprint = Self::signature_key_id(&jws_header)?;
+        Self::check_signed_recipient(&Self::header_claims(&jws_header)?, audience)?;
+        let forwarder = match self.config.friend_by_key_id(forwarder_fingerprint) {
+            Some(friend) => {
+                Self::check_not_revoked(friend)?;
+                Self::verify_detached_jws(
+                    header_b64,
+                    &jws_header,
+                    &signature,
+                    message,
+                    friend.public_key(),
+                )?;
+                Some(friend.name().to_string())
+            }
+            None if options.allow_unknown_sender => {
+                let forwarder_key =
+                    Self::embedded_sender_key(jws_header.claim("jwk"), forwarder_fingerprint)?
+                        .ok_or_else(|| {
+                            EncItError::FriendNotFound(
+                                "the forwarder public key is not embedded in the message"
+                                    .to_string(),
+                            )
+                        })?;
+                Self::verify_detached_jws(
+                    header_b64,
+                    &jws_header,
+                    &signature,
+                    message,
+                    &forwarder_key,
+                )?;
+                None
+            }
+            None => {
+                return Err(EncItError::FriendNotFound(
+                    "cannot find a friend that match with the forwarder public key".to_string(),
+                ))
+            }
+        };
+        Ok(Some((forwarder, forwarder_fingerprint.to_string())))
+    }
+
+    fn forwarded_message(
+        message: EncItMessage,
+        forwarder: Option<(Option<String>, String)>,
+    ) -> EncItMessage {
+        match forwarder {
+            Some((forwarded_by, forwarder_fingerprint)) => EncItMessage {
+                forwarded_by,
+                forwarder_fingerprint: Some(forwarder_fingerprint),
+                ..message
+            },
+            None => message,
+        }
+    }
+
+    /// the decrypting identity has to be one of the signed recipients,
+    /// the audience contains the ids of the receiving key
+    fn check_signed_recipient(claims: &JwtPayload, audience: &[String]) -> Result<(), EncItError> {
+        if !claims.audience().is_some_and(|signed_audience| {
+            signed_audience
+                .iter()
+                .any(|signed| audience.iter().any(|key_id| key_id == signed))
+        }) {
+            return Err(EncItError::InvalidRecipient());
+        }
+        Ok(())
+    }
+
+    fn legacy_payload(payload: &[u8]) -> Result<JwtPayload, EncItError> {
+        let payload: Map<String, Value> = serde_json::from_slice(payload)?;
+        JwtPayload::from_map(payload).map_err(|e| e.into())
+    }
+
+    fn create_binary_payload(
+        envelope: &EncItEnvelope,
+        message: &[u8],
+    ) -> Result<Vec<u8>, EncItError> {
+        let envelope = serde_json::to_vec(envelope)?;
+        let mut payload = Vec::with_capacity(ENVELOPE_LEN_SIZE + envelope.len() + message.len());
+        payload.extend_from_slice(&(envelope.len() as u32).to_be_bytes());
+        payload.extend_from_slice(&envelope);
+        payload.extend_from_slice(message);
+        Ok(payload)
+    }
+
+    fn split_binary_payload(payload: &[u8]) -> Result<(EncItEnvelope, &[u8]), EncItError> {
+        let invalid_payload = || EncItError::DecodeError("invalid message payload".to_string());
+        if payload.len() < ENVELOPE_LEN_SIZE {
+            return Err(invalid_payload());
+        }
+        let (envelope_len, payload) = payload.split_at(ENVELOPE_LEN_SIZE);
+        let envelope_len = u32::from_be_bytes(envelope_len.try_into().unwrap()) as usize;
+        if payload.len() < envelope_len {
+            return Err(invalid_payload());
+        }
+        let (envelope, message) = payload.split_at(envelope_len);
+        Ok((serde_json::from_slice(envelope)?, message))
+    }
+
+    /// records the message id, a replayed burn after reading message is refused
+    fn check_replay(
+        &self,
+        message: EncItMessage,
+        options: &EncItDecryptOptions,
+    ) -> Result<EncItMessage, EncItError> {
+        let (seen_messages, id) = match (&self.seen_messages, message.id()) {
+            (Some(seen_messages), Some(id)) => (seen_messages, id),
+            _ => return Ok(message),
+        };
+        if !seen_messages.seen(id)? {
+            if !options.defer_seen {
+                seen_messages.add(id)?;
+            }
+            return Ok(message);
+        }
+        if message.burn_after_reading {
+            return Err(EncItError::MessageAlreadyRead(id.to_string()));
+        }
+        Ok(EncItMessage {
+            replayed: true,
+            ..message
+        })
+    }
+
+    /// the JWE header is readable without a key, the subject is in the encrypted claims
+    fn create_jwe(
+        payload: &[u8],
+        friends: &[&EncItFriend],
+        compress: bool,
+        hide_recipients: bool,
+    ) -> Result<String, EncItError> {
+        if let [friend] = friends {
+            let mut jwe_header = JweHeader::new();
+            jwe_header.set_content_type(BINARY_PAYLOAD_CONTENT_TYPE);
+            jwe_header.set_content_encryption("A128CBC-HS256");
+            if compress {
+                jwe_header.set_compression(DEFLATE);
+            }
+            if !hide_recipients {
+                jwe_header.set_key_id(friend.public_key().thumbprint()?);
+            }
+            let encrypter = Self::encrypter(friend.public_key())?;
+            return jwe::serialize_compact(payload, &jwe_header, &*encrypter).map_err(|e| e.into());
+        }
+
+        // more than one friend: the content key is wrapped once per recipient
+        // using the JWE general JSON serialization
+        let mut jwe_header = JweHeaderSet::new();
+        jwe_header.set_content_type(BINARY_PAYLOAD_CONTENT_TYPE, true);
+        jwe_header.set_content_encryption("A128CBC-HS256", true);
+        if compress {
+            jwe_header.set_compression(DEFLATE);
+        }
+        let mut recipient_headers = Vec::with_capacity(friends.len());
+        let mut encrypters = Vec::with_capacity(friends.len());
+        for friend in friends {
+            let mut recipient_header = JweHeader::new();
+            if !hide_recipients {
+                recipient_header.set_key_id(friend.public_key().thumbprint()?);
+            }
+            recipient_headers.push(recipient_header);
+            encrypters.push(Self::encrypter(friend.public_key())?);
+        }
+        let recipients: Vec<(Option<&JweHeader>, &dyn JweEncrypter)> = recipient_headers
+            .iter()
+            .zip(encrypters.iter())
+            .map(|(header, encrypter)| (Some(header), &**encrypter))
+            .collect();
+        jwe::serialize_general_json(payload, Some(&jwe_header), &recipients, None)
+            .map_err(|e| e.into())
+    }
+
+    fn create_password_jwe(
+        payload: &[u8],
+        password: &EncItPassword,
+        iterations: u32,
+        compress: bool,
     ) -> Result<String, EncItError> {
-        let friend_pub_key = friend.public_key().pem()?;
-        let identity_pub_key_sha = identity.private_key().public_key_pem_sha()?;
         let mut jwe_header = JweHeader::new();
-        jwe_header.set_token_type("JWT");
-        jwe_header.set_content_encryption("A128CBC-HS256");
-        if let Some(subject) = subject {
-            jwe_header.set_subject(subject);
+        jwe_header.set_content_type(BINARY_PAYLOAD_CONTENT_TYPE);
+        jwe_header.set_content_encryption("A256CBC-HS512");
+        if compress {
+            jwe_header.set_compression(DEFLATE);
         }
-        jwe_header.set_claim("rcp", Some(friend.public_key().sha_pem()?.into()))?;
-        let mut payload = JwtPayload::new();
-        payload.set_issuer(identity_pub_key_sha);
-        payload.set_claim("message", Some(Value::String(message.to_string())))?;
-        let encrypter = RSA_OAEP.encrypter_from_pem(friend_pub_key)?;
-        jwt::encode_with_encrypter(&payload, &jwe_header, &encrypter).map_err(|e| e.into())
+        let encrypter = Self::password_encrypter(password, iterations)?;
+        jwe::serialize_compact(payload, &jwe_header, &*encrypter).map_err(|e| e.into())
+    }
+
+    fn verify_clear_signed(
+        &self,
+        clear_signed: &str,
+        options: &EncItDecryptOptions,
+    ) -> Result<EncItMessage, EncItError> {
+        let invalid_format = || EncItError::DecodeError("invalid clear-signed message".to_string());
+        let (_, clear_signed) = clear_signed
+            .split_once(&format!("{}\n", CLEAR_SIGNED_BEGIN))
+            .ok_or_else(invalid_format)?;
+        // the plaintext can contain anything, the signature block is the last one
+        let (message, signature) = clear_signed
+            .rsplit_once(&format!("\n{}\n", CLEAR_SIGNATURE_BEGIN))
+            .ok_or_else(invalid_format)?;
+        let (signature, _) = signature
+            .split_once(CLEAR_SIGNATURE_END)
+            .ok_or_else(invalid_format)?;
+        let jws: String = signature.split_whitespace().collect();
+
+        let friend = jwt::decode_header(&jws)?
+            .claim("kid")
+            .and_then(|kid| kid.as_str())
+            .and_then(|friend_pub_key_sha| self.config.friend_by_key_id(friend_pub_key_sha))
+            .ok_or_else(|| {
+                EncItError::FriendNotFound(
+                    "cannot find a friend that match with the message public key".to_string(),
+                )
+            })?;
+        Self::check_not_revoked(friend)?;
+        let content = Self::extract_jws(Some(&jws), friend)?;
+        let signed_message = EncItMessage {
+            sender: Some(friend.name().to_string()),
+            sender_trust: Some(friend.trust()),
+            sender_fingerprint: Some(friend.public_key().thumbprint()?),
+            signed: true,
+            verified: true,
+            ..Self::message_from_content(content, String::new(), None, options)?
+        };
+        // the readable plaintext has to be the signed one
+        if signed_message.payload() != message.as_bytes() {
+            return Err(EncItError::InvalidSignature());
+        }
+        Ok(signed_message)
     }
 
-    fn create_jws(message: &str, identity: &EncItIdentity) -> Result<String, EncItError> {
+    fn create_jws(
+        content: &JwtPayload,
+        identity: &EncItIdentity,
+        embed_key: bool,
+    ) -> Result<String, EncItError> {
         let identity_priv_key = identity.private_key().pem()?;
         debug!(
             "signing with private key:{}",
@@ -174,71 +1255,482 @@ impl EncItImpl {
         );
         let mut jws_header = JwsHeader::new();
         jws_header.set_token_type("JWT");
+        jws_header.set_key_id(identity.private_key().public_key_thumbprint()?);
+        if embed_key {
+            jws_header.set_jwk(identity.private_key().public_key_jwk()?);
+        }
 
-        let mut payload = JwtPayload::new();
-        payload.set_claim("message", Some(message.into()))?;
-
-        let signer = RS256.signer_from_pem(identity_priv_key)?;
-        jwt::encode_with_signer(&payload, &jws_header, &signer).map_err(|e| e.into())
+        let signer = Self::signer(identity.private_key())?;
+        jwt::encode_with_signer(content, &jws_header, &*signer).map_err(|e| e.into())
     }
 
-    fn extract_jwe(
-        jwe: &str,
+    /// detached JWS with unencoded payload (RFC 7797), the claims are replicated
+    /// in the protected header (RFC 7519 section 5.3)
+    fn create_detached_jws(
         identity: &EncItIdentity,
-    ) -> Result<(JwtPayload, JweHeader), EncItError> {
-        let decrypter = RSA_OAEP.decrypter_from_pem(identity.private_key().pem()?)?;
-        jwt::decode_with_decrypter(jwe.trim(), &decrypter).map_err(|e| e.into())
+        claims: &JwtPayload,
+        embed_key: bool,
+        message: &[u8],
+    ) -> Result<String, EncItError> {
+        let signer = Self::signer(identity.private_key())?;
+        let mut jws_header = JwsHeader::new();
+        jws_header.set_algorithm(signer.algorithm().name());
+        jws_header.set_base64url_encode_payload(false);
+        jws_header.set_critical(&vec!["b64"]);
+        jws_header.set_key_id(identity.private_key().public_key_thumbprint()?);
+        if embed_key {
+            jws_header.set_jwk(identity.private_key().public_key_jwk()?);
+        }
+        for (key, value) in claims.claims_set() {
+            jws_header.set_claim(key, Some(value.clone()))?;
+        }
+        let header_b64 = base64::encode_config(
+            serde_json::to_vec(jws_header.claims_set())?,
+            base64::URL_SAFE_NO_PAD,
+        );
+        let signature = signer.sign(&Self::signing_input(&header_b64, message))?;
+        Ok(format!(
+            "{}..{}",
+            header_b64,
+            base64::encode_config(signature, base64::URL_SAFE_NO_PAD)
+        ))
     }
 
-    fn extract_jws(jws: Option<&str>, friend: &EncItFriend) -> Result<(bool, String), EncItError> {
-        let jws = jws.ok_or_else(EncItError::EmptyMessage)?;
-        debug!("extract jws :{}", jws);
-        let friend_public_key = friend.public_key().pem()?;
-        debug!(
-            "verifying with friend public key:{}",
-            String::from_utf8(friend_public_key.clone()).unwrap()
-        );
-        let verifier = RS256.verifier_from_pem(friend_public_key)?;
-        jwt::decode_with_verifier(jws, &verifier)
-            .map(|(payload, _)| {
-                (
-                    true,
-                    payload
-                        .claim("message")
-                        .unwrap()
-                        .as_str()
-                        .unwrap()
-                        .to_string(),
-                )
-            })
+    fn transition_header(key_id: &str) -> JwsHeaderSet {
+        let mut header = JwsHeaderSet::new();
+        header.set_token_type(TRANSITION_TYPE, true);
+        header.set_key_id(key_id, true);
+        header
+    }
+
+    /// the statement payload, before the signatures are verified
+    fn parse_transition(statement: &str) -> Result<EncItTransition, EncItError> {
+        let statement: Map<String, Value> = serde_json::from_str(statement.trim())?;
+        let payload = statement
+            .get("payload")
+            .and_then(|payload| payload.as_str())
+            .ok_or_else(|| EncItError::DecodeError("not a transition statement".to_string()))?;
+        serde_json::from_slice(&base64::decode_config(payload, base64::URL_SAFE_NO_PAD)?)
             .map_err(|e| e.into())
     }
-}
 
-#[cfg(test)]
-pub mod tests {
-    use mockall::predicate::eq;
-    use openssl::pkey::Private;
-    use openssl::rsa::Rsa;
+    /// the encoded header, the header and the signature of a detached JWS
+    fn parse_detached_jws(jws: &str) -> Result<(&str, JwsHeader, Vec<u8>), EncItError> {
+        let (header_b64, signature_b64) = match jws.trim().split('.').collect::<Vec<_>>()[..] {
+            [header_b64, "", signature_b64] => (header_b64, signature_b64),
+            _ => {
+                return Err(EncItError::DecodeError(
+                    "not a detached signature".to_string(),
+                ))
+            }
+        };
+        let jws_header: Map<String, Value> =
+            serde_json::from_slice(&base64::decode_config(header_b64, base64::URL_SAFE_NO_PAD)?)?;
+        let jws_header = JwsHeader::from_map(jws_header)?;
+        if jws_header.base64url_encode_payload() != Some(false)
+            || !jws_header
+                .critical()
+                .is_some_and(|critical| critical.contains(&"b64"))
+        {
+            return Err(EncItError::DecodeError(
+                "the signature payload has to be unencoded".to_string(),
+            ));
+        }
+        let signature = base64::decode_config(signature_b64, base64::URL_SAFE_NO_PAD)?;
+        Ok((header_b64, jws_header, signature))
+    }
 
-    use crate::config::{EncItFriend, EncItIdentity, EncItPEM, EncItPrivateKey, MockEncItConfig};
+    fn verify_detached_jws(
+        header_b64: &str,
+        jws_header: &JwsHeader,
+        signature: &[u8],
+        message: &[u8],
+        public_key: &EncItPEM,
+    ) -> Result<(), EncItError> {
+        let verifier = Self::verifier(public_key)?;
+        if jws_header.algorithm() != Some(verifier.algorithm().name()) {
+            return Err(EncItError::InvalidSignature());
+        }
+        verifier
+            .verify(&Self::signing_input(header_b64, message), signature)
+            .map_err(|_| EncItError::InvalidSignature())
+    }
+
+    /// thumbprint of the signer public key, the public key sha for older versions
+    fn signature_key_id(jws_header: &JwsHeader) -> Result<&str, EncItError> {
+        jws_header
+            .key_id()
+            .ok_or_else(|| EncItError::DecodeError("the signature key id is missing".to_string()))
+    }
+
+    /// the message claims replicated in the signature header
+    fn header_claims(jws_header: &JwsHeader) -> Result<JwtPayload, EncItError> {
+        let claims: Map<String, Value> = jws_header
+            .claims_set()
+            .iter()
+            .filter(|(key, _)| MESSAGE_CLAIMS.contains(&key.as_str()))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        JwtPayload::from_map(claims).map_err(|e| e.into())
+    }
+
+    /// the receiver identity and the decrypted JWE, when the message hides the recipients
+    /// every identity is tried in turn
+    fn extract_receiver_jwe(
+        &self,
+        jwe: &str,
+        identity: Option<&str>,
+    ) -> Result<(&EncItIdentity, Vec<u8>, JweHeader), EncItError> {
+        if let Some(identity_name) = identity {
+            let identity = self
+                .config
+                .identity(identity_name)
+                .ok_or_else(|| EncItError::IdentityNotFound(identity_name.to_string()))?;
+            let (payload, header) = Self::extract_jwe(jwe, identity)?;
+            return Ok((identity, payload, header));
+        }
+        let recipients = Self::recipients_key_ids(jwe)?;
+        if let Some(identity) = recipients.iter().find_map(|receiver_public_key_sha| {
+            debug!("get identity by sha:{}", receiver_public_key_sha);
+            self.config.identity_by_key_id(receiver_public_key_sha)
+        }) {
+            debug!("Identity found:{}", identity.name());
+            let (payload, header) = Self::extract_jwe(jwe, identity)?;
+            return Ok((identity, payload, header));
+        }
+        if Self::has_hidden_recipients(jwe)? {
+            for identity in self.config.identities() {
+                debug!("trial decryption with identity:{}", identity.name());
+                if let Ok((payload, header)) = Self::extract_jwe(jwe, identity) {
+                    return Ok((identity, payload, header));
+                }
+            }
+        }
+        Err(EncItError::IdentityNotFound(String::new()))
+    }
+
+    /// the messages sent before a rotation are decrypted with the previous keys
+    fn extract_jwe(
+        jwe: &str,
+        identity: &EncItIdentity,
+    ) -> Result<(Vec<u8>, JweHeader), EncItError> {
+        let mut result = Err(EncItError::IdentityNotFound(identity.name().to_string()));
+        for private_key in identity.keys() {
+            result = Self::extract_jwe_with_key(jwe, identity.name(), private_key);
+            if result.is_ok() {
+                break;
+            }
+        }
+        result
+    }
+
+    fn extract_jwe_with_key(
+        jwe: &str,
+        identity_name: &str,
+        private_key: &EncItPrivateKey,
+    ) -> Result<(Vec<u8>, JweHeader), EncItError> {
+        let decrypter = Self::decrypter(private_key)?;
+        // the default DEFLATE decompression has no size limit
+        let mut jwe_context = JweContext::new();
+        jwe_context.add_compression(Box::new(BoundedDeflate::new(MAX_DECOMPRESSED_LEN)));
+        if !Self::is_json_serialized(jwe) {
+            return jwe_context
+                .deserialize_compact(jwe, &*decrypter)
+                .map_err(|e| e.into());
+        }
+        let identity_key_ids = private_key.key_ids()?;
+        let recipients_count = Self::json_recipients(jwe)?.len();
+        let mut result = Err(EncItError::IdentityNotFound(identity_name.to_string()));
+        // the recipients are tried one at a time, a hidden recipient (without kid)
+        // is tried if the key algorithm matches
+        for recipient_index in 0..recipients_count {
+            let index = Cell::new(0);
+            result = jwe_context
+                .deserialize_json_with_selector(jwe, |header| {
+                    index.set(index.get() + 1);
+                    if index.get() - 1 != recipient_index {
+                        return Ok(None);
+                    }
+                    match Self::recipient_key_id(|name| header.claim(name)) {
+                        Some(key_id) if identity_key_ids.iter().any(|id| id == key_id) => {
+                            Ok(Some(&*decrypter))
+                        }
+                        None if header.algorithm() == Some(decrypter.algorithm().name()) => {
+                            Ok(Some(&*decrypter))
+                        }
+                        _ => Ok(None),
+                    }
+                })
+                .map_err(|e| e.into());
+            if result.is_ok() {
+                break;
+            }
+        }
+        result
+    }
+
+    fn extract_password_jwe(
+        jwe: &str,
+        password: &EncItPassword,
+    ) -> Result<(Vec<u8>, JweHeader), EncItError> {
+        let iterations = jwt::decode_header(jwe)?
+            .claim("p2c")
+            .and_then(|p2c| p2c.as_u64())
+            .unwrap_or_default();
+        if iterations > PBES2_MAX_ITERATIONS {
+            return Err(EncItError::DecodeError(format!(
+                "too many password iterations: {}",
+                iterations
+            )));
+        }
+        let mut jwe_context = JweContext::new();
+        jwe_context.add_compression(Box::new(BoundedDeflate::new(MAX_DECOMPRESSED_LEN)));
+        jwe_context
+            .deserialize_compact(jwe, &*Self::password_decrypter(password)?)
+            .map_err(|_| EncItError::InvalidPassword())
+    }
+
+    fn signing_input(header_b64: &str, message: &[u8]) -> Vec<u8> {
+        let mut signing_input = Vec::with_capacity(header_b64.len() + 1 + message.len());
+        signing_input.extend_from_slice(header_b64.as_bytes());
+        signing_input.push(b'.');
+        signing_input.extend_from_slice(message);
+        signing_input
+    }
+
+    fn is_json_serialized(jwe: &str) -> bool {
+        jwe.starts_with('{')
+    }
+
+    /// the recipient key id, `rcp` for the messages of older versions
+    fn recipient_key_id<'a>(claim: impl Fn(&str) -> Option<&'a Value>) -> Option<&'a str> {
+        claim("kid")
+            .or_else(|| claim("rcp"))
+            .and_then(|key_id| key_id.as_str())
+    }
+
+    fn recipients_key_ids(jwe: &str) -> Result<Vec<String>, EncItError> {
+        if !Self::is_json_serialized(jwe) {
+            let header = jwt::decode_header(jwe)?;
+            return Ok(Self::recipient_key_id(|name| header.claim(name))
+                .map(|key_id| key_id.to_string())
+                .into_iter()
+                .collect());
+        }
+        Ok(Self::json_recipients(jwe)?
+            .iter()
+            .filter_map(|recipient| recipient.get("header"))
+            .filter_map(|header| header.as_object())
+            .filter_map(|header| Self::recipient_key_id(|name| header.get(name)))
+            .map(|key_id| key_id.to_string())
+            .collect())
+    }
+
+    fn json_recipients(jwe: &str) -> Result<Vec<Value>, EncItError> {
+        let mut jwe: Map<String, Value> = serde_json::from_str(jwe)?;
+        match jwe.remove("recipients") {
+            Some(Value::Array(recipients)) => Ok(recipients),
+            _ => Err(EncItError::DecodeError("recipients not found".to_string())),
+        }
+    }
+
+    /// at least one recipient without key id
+    fn has_hidden_recipients(jwe: &str) -> Result<bool, EncItError> {
+        if !Self::is_json_serialized(jwe) {
+            let header = jwt::decode_header(jwe)?;
+            return Ok(Self::recipient_key_id(|name| header.claim(name)).is_none());
+        }
+        Ok(Self::json_recipients(jwe)?.iter().any(|recipient| {
+            recipient
+                .get("header")
+                .and_then(|header| header.as_object())
+                .and_then(|header| Self::recipient_key_id(|name| header.get(name)))
+                .is_none()
+        }))
+    }
+
+    fn extract_jws(jws: Option<&str>, friend: &EncItFriend) -> Result<JwtPayload, EncItError> {
+        let jws = jws.ok_or_else(EncItError::EmptyMessage)?;
+        debug!("extract jws :{}", jws);
+        let friend_public_key = friend.public_key().pem()?;
+        debug!(
+            "verifying with friend public key:{}",
+            String::from_utf8(friend_public_key.clone()).unwrap()
+        );
+        let verifier = Self::verifier(friend.public_key())?;
+        jwt::decode_with_verifier(jws, &*verifier)
+            .map(|(payload, _)| payload)
+            .map_err(|e| e.into())
+    }
+
+    /// the sender JWK embedded in the JWS header, it has to match the sender fingerprint
+    fn embedded_sender_key(
+        jwk: Option<&Value>,
+        sender_fingerprint: &str,
+    ) -> Result<Option<EncItPEM>, EncItError> {
+        let jwk = match jwk {
+            Some(Value::Object(jwk)) => Jwk::from_map(jwk.clone())?,
+            _ => return Ok(None),
+        };
+        let sender_key = EncItPEM::from_jwk(&jwk)?;
+        if !sender_key
+            .key_ids()?
+            .iter()
+            .any(|key_id| key_id == sender_fingerprint)
+        {
+            return Err(EncItError::InvalidSignature());
+        }
+        Ok(Some(sender_key))
+    }
+
+    /// the signature is checked only if the sender embedded the public key,
+    /// the sender identity is not verified anyway. The flag tells if the signature was checked
+    fn extract_unknown_sender_jws(
+        jws: Option<&str>,
+        sender_fingerprint: &str,
+    ) -> Result<(JwtPayload, bool), EncItError> {
+        let jws = jws.ok_or_else(EncItError::EmptyMessage)?;
+        if let Some(sender_key) =
+            Self::embedded_sender_key(jwt::decode_header(jws)?.claim("jwk"), sender_fingerprint)?
+        {
+            let payload =
+                Self::extract_jws(Some(jws), &EncItFriend::new(String::new(), sender_key))?;
+            return Ok((payload, true));
+        }
+        let payload = jws
+            .split('.')
+            .nth(1)
+            .ok_or_else(|| EncItError::DecodeError("invalid jws".to_string()))?;
+        let payload: Map<String, Value> =
+            serde_json::from_slice(&base64::decode_config(payload, base64::URL_SAFE_NO_PAD)?)?;
+        Ok((JwtPayload::from_map(payload)?, false))
+    }
+
+    /// the key encryption algorithm depends on the recipient key type
+    fn encrypter(public_key: &EncItPEM) -> Result<Box<dyn JweEncrypter>, EncItError> {
+        let pem = public_key.encryption_key_pem()?;
+        let encrypter: Box<dyn JweEncrypter> = match public_key.key_type()? {
+            EncItKeyType::Rsa => Box::new(RSA_OAEP.encrypter_from_pem(pem)?),
+            EncItKeyType::EcP256 | EncItKeyType::EcP384 | EncItKeyType::Ed25519 => {
+                Box::new(ECDH_ES_A256KW.encrypter_from_pem(pem)?)
+            }
+        };
+        Ok(encrypter)
+    }
+
+    fn decrypter(private_key: &EncItPrivateKey) -> Result<Box<dyn JweDecrypter>, EncItError> {
+        let pem = private_key.encryption_key_pem()?;
+        let decrypter: Box<dyn JweDecrypter> = match private_key.key_type()? {
+            EncItKeyType::Rsa => Box::new(RSA_OAEP.decrypter_from_pem(pem)?),
+            EncItKeyType::EcP256 | EncItKeyType::EcP384 | EncItKeyType::Ed25519 => {
+                Box::new(ECDH_ES_A256KW.decrypter_from_pem(pem)?)
+            }
+        };
+        Ok(decrypter)
+    }
+
+    fn password_encrypter(
+        password: &EncItPassword,
+        iterations: u32,
+    ) -> Result<Box<dyn JweEncrypter>, EncItError> {
+        if iterations < PBES2_MIN_ITERATIONS {
+            return Err(EncItError::InvalidCommand(format!(
+                "the password iterations have to be at least {}",
+                PBES2_MIN_ITERATIONS
+            )));
+        }
+        let mut encrypter = PBES2_HS512_A256KW.encrypter_from_bytes(password.0.as_bytes())?;
+        encrypter.set_iter_count(iterations as usize);
+        Ok(Box::new(encrypter))
+    }
+
+    fn password_decrypter(password: &EncItPassword) -> Result<Box<dyn JweDecrypter>, EncItError> {
+        Ok(Box::new(
+            PBES2_HS512_A256KW.decrypter_from_bytes(password.0.as_bytes())?,
+        ))
+    }
+
+    /// the signature algorithm depends on the sender key type
+    fn signer(private_key: &EncItPrivateKey) -> Result<Box<dyn JwsSigner>, EncItError> {
+        let pem = private_key.pem()?;
+        let signer: Box<dyn JwsSigner> = match private_key.key_type()? {
+            EncItKeyType::Rsa => Box::new(RS256.signer_from_pem(pem)?),
+            EncItKeyType::EcP256 => Box::new(ES256.signer_from_pem(pem)?),
+            EncItKeyType::EcP384 => Box::new(ES384.signer_from_pem(pem)?),
+            EncItKeyType::Ed25519 => Box::new(EdDSA.signer_from_pem(pem)?),
+        };
+        Ok(signer)
+    }
+
+    fn verifier(public_key: &EncItPEM) -> Result<Box<dyn JwsVerifier>, EncItError> {
+        let pem = public_key.public_key_pem()?;
+        let verifier: Box<dyn JwsVerifier> = match public_key.key_type()? {
+            EncItKeyType::Rsa => Box::new(RS256.verifier_from_pem(pem)?),
+            EncItKeyType::EcP256 => Box::new(ES256.verifier_from_pem(pem)?),
+            EncItKeyType::EcP384 => Box::new(ES384.verifier_from_pem(pem)?),
+            EncItKeyType::Ed25519 => Box::new(EdDSA.verifier_from_pem(pem)?),
+        };
+        Ok(verifier)
+    }
+}
+
+fn serialize_base64<S: Serializer>(payload: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&base64::encode(payload))
+}
+
+fn deserialize_base64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    base64::decode(String::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+}
+
+fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::seen::MockEncItSeenMessages;
+    use mockall::predicate::eq;
+    use openssl::pkey::{PKey, Private};
+    use std::sync::{Arc, Mutex};
+
+    use crate::config::{EncItFriend, EncItIdentity, EncItPEM, EncItPrivateKey, MockEncItConfig};
 
     use super::*;
 
     #[test]
     fn encrypt_decrypt_payload() -> Result<(), EncItError> {
+        check_encrypt_decrypt(None, None)
+    }
+
+    #[test]
+    fn encrypt_decrypt_ed25519_payload() -> Result<(), EncItError> {
+        check_encrypt_decrypt(
+            Some(EncItKeyType::Ed25519.generate()?),
+            Some(EncItKeyType::Ed25519.generate()?),
+        )
+    }
+
+    fn check_encrypt_decrypt(
+        identity_key: Option<PKey<Private>>,
+        friend_key: Option<PKey<Private>>,
+    ) -> Result<(), EncItError> {
         let encrypt_friend_name = "bob";
         let encrypt_identity_name = "alice";
         let (encrypt_friend_private_key, encrypt_friend) =
-            generate_friend(encrypt_friend_name, None);
+            generate_friend(encrypt_friend_name, friend_key);
         let (encrypt_identity_private_key, encrypt_identity) =
-            generate_identity(encrypt_identity_name, None);
+            generate_identity(encrypt_identity_name, identity_key);
         let encrypt_friend = Box::leak(encrypt_friend);
         let encrypt_identity = Box::leak(encrypt_identity);
         let encrypt_friend_public_key_sha =
-            Box::leak(Box::new(encrypt_friend.public_key().sha_pem().unwrap()));
+            Box::leak(Box::new(encrypt_friend.public_key().thumbprint().unwrap()));
         let encrypt_identity_public_key_sha = Box::leak(Box::new(
-            encrypt_identity.private_key().public_key_pem_sha().unwrap(),
+            encrypt_identity
+                .private_key()
+                .public_key_thumbprint()
+                .unwrap(),
         ));
         let mut encrypt_cfg_mock = MockEncItConfig::new();
         encrypt_cfg_mock
@@ -253,14 +1745,29 @@ pub mod tests {
         let encrypt_cfg: Rc<dyn EncItConfig> = Rc::new(encrypt_cfg_mock);
         let enc_it = EncItImpl::new(encrypt_cfg);
 
-        let plain_message = "hello";
+        let plain_message = b"hello";
+        let file_metadata = EncItFileMetadata {
+            name: Some("hello.txt".to_string()),
+            content_type: Some("text/plain".to_string()),
+            size: Some(5),
+            mtime: Some(1_600_000_000),
+            mode: Some(0o644),
+        };
         let enc_msg = enc_it.encrypt(
-            encrypt_identity_name,
-            encrypt_friend_name,
-            Some("subject"),
+            Some(encrypt_identity_name),
+            &[encrypt_friend_name],
+            &EncItEncryptOptions {
+                subject: Some("subject".to_string()),
+                expires_in: Some(Duration::from_secs(60)),
+                file: Some(file_metadata.clone()),
+                ..Default::default()
+            },
             plain_message,
         )?;
 
+        // the subject is not readable without the key
+        assert!(jwt::decode_header(&enc_msg)?.claim("sub").is_none());
+
         // decrypt
         let (_, decrypt_friend) =
             generate_friend(encrypt_identity_name, Some(encrypt_identity_private_key));
@@ -271,32 +1778,1009 @@ pub mod tests {
         let mut decrypt_cfg_mock = MockEncItConfig::new();
 
         decrypt_cfg_mock
-            .expect_identity_by_public_key_sha()
+            .expect_identity_by_key_id()
             .with(eq(encrypt_friend_public_key_sha.as_str()))
             .returning(|_| Some(decrypt_identity));
         decrypt_cfg_mock
-            .expect_friend_by_public_key_sha()
+            .expect_friend_by_key_id()
             .with(eq(encrypt_identity_public_key_sha.as_str()))
             .returning(|_| Some(decrypt_friend));
 
         let decrypt_cfg_mock: Rc<dyn EncItConfig> = Rc::new(decrypt_cfg_mock);
         let enc_it = EncItImpl::new(decrypt_cfg_mock);
-        let decrypted = enc_it.decrypt(&enc_msg, None);
+        let decrypted = enc_it.decrypt(&enc_msg, None, &EncItDecryptOptions::default());
 
         let message = decrypted?;
         assert_eq!(message.payload, plain_message);
         assert_eq!(message.subject, Some("subject".to_string()));
         assert!(message.verified);
-        assert_eq!(message.sender, encrypt_identity.name());
+        assert_eq!(message.sender(), Some(encrypt_identity.name()));
+        assert_eq!(message.sender_trust(), Some(EncItTrustLevel::Unverified));
         assert_eq!(message.receiver, encrypt_friend.name());
+        let issued_at = message.issued_at().unwrap();
+        assert_eq!(message.expires_at(), Some(issued_at + 60));
+        assert_eq!(message.file(), Some(&file_metadata));
+        Ok(())
+    }
+
+    #[test]
+    fn encrypt_revoked_friend() -> Result<(), EncItError> {
+        let (_, alice) = generate_identity("alice", Some(EncItKeyType::Ed25519.generate()?));
+        let alice: &'static EncItIdentity = Box::leak(alice);
+        let (_, bob) = generate_friend("bob", Some(EncItKeyType::Ed25519.generate()?));
+        let mut bob = serde_json::to_value(&*bob)?;
+        bob["trust"] = serde_json::json!("revoked");
+        let bob: &'static EncItFriend = Box::leak(Box::new(serde_json::from_value(bob)?));
+        let mut cfg_mock = MockEncItConfig::new();
+        cfg_mock
+            .expect_identity()
+            .with(eq("alice"))
+            .returning(move |_| Some(alice));
+        cfg_mock
+            .expect_friend()
+            .with(eq("bob"))
+            .returning(move |_| Some(bob));
+        let enc_it = EncItImpl::new(Rc::new(cfg_mock));
+        let encrypted = enc_it.encrypt(
+            Some("alice"),
+            &["bob"],
+            &EncItEncryptOptions::default(),
+            b"hello",
+        );
+        assert!(matches!(encrypted, Err(EncItError::FriendRevoked(name)) if name == "bob"));
+        Ok(())
+    }
+
+    #[test]
+    fn decrypt_revoked_sender() -> Result<(), EncItError> {
+        let (alice_private_key, alice) =
+            generate_identity("alice", Some(EncItKeyType::Ed25519.generate()?));
+        let alice: &'static EncItIdentity = Box::leak(alice);
+        let (bob_private_key, bob) = generate_friend("bob", None);
+        let bob: &'static EncItFriend = Box::leak(bob);
+        let mut encrypt_cfg_mock = MockEncItConfig::new();
+        encrypt_cfg_mock
+            .expect_identity()
+            .returning(move |_| Some(alice));
+        encrypt_cfg_mock
+            .expect_friend()
+            .returning(move |_| Some(bob));
+        let enc_msg = EncItImpl::new(Rc::new(encrypt_cfg_mock)).encrypt(
+            Some("alice"),
+            &["bob"],
+            &EncItEncryptOptions::default(),
+            b"hello",
+        )?;
+
+        let (_, decrypt_identity) = generate_identity("bob", Some(bob_private_key));
+        let decrypt_identity: &'static EncItIdentity = Box::leak(decrypt_identity);
+        let (_, decrypt_friend) = generate_friend("alice", Some(alice_private_key));
+        let mut decrypt_friend = serde_json::to_value(&*decrypt_friend)?;
+        decrypt_friend["trust"] = serde_json::json!("revoked");
+        let decrypt_friend: &'static EncItFriend =
+            Box::leak(Box::new(serde_json::from_value(decrypt_friend)?));
+        let mut decrypt_cfg_mock = MockEncItConfig::new();
+        decrypt_cfg_mock
+            .expect_identity_by_key_id()
+            .returning(move |_| Some(decrypt_identity));
+        decrypt_cfg_mock
+            .expect_friend_by_key_id()
+            .returning(move |_| Some(decrypt_friend));
+        let message = EncItImpl::new(Rc::new(decrypt_cfg_mock)).decrypt(
+            &enc_msg,
+            None,
+            &EncItDecryptOptions::default(),
+        )?;
+        assert_eq!(message.payload(), b"hello");
+        assert_eq!(message.sender_trust(), Some(EncItTrustLevel::Revoked));
+        assert!(!message.verified());
+        Ok(())
+    }
+
+    #[test]
+    fn binary_payload() -> Result<(), EncItError> {
+        let (alice_private_key, alice) =
+            generate_identity("alice", Some(EncItKeyType::Ed25519.generate()?));
+        let alice: &'static EncItIdentity = Box::leak(alice);
+        let (bob_private_key, bob) = generate_friend("bob", None);
+        let bob: &'static EncItFriend = Box::leak(bob);
+        let mut encrypt_cfg_mock = MockEncItConfig::new();
+        encrypt_cfg_mock
+            .expect_identity()
+            .returning(move |_| Some(alice));
+        encrypt_cfg_mock
+            .expect_friend()
+            .returning(move |_| Some(bob));
+        let mut plain_message = vec![0u8; 30_000];
+        rand_bytes(&mut plain_message)?;
+        let enc_msg = EncItImpl::new(Rc::new(encrypt_cfg_mock)).encrypt(
+            Some("alice"),
+            &["bob"],
+            &EncItEncryptOptions::default(),
+            &plain_message,
+        )?;
+        // only the JWE compact serialization encodes the ciphertext
+        assert!(enc_msg.len() < plain_message.len() * 4 / 3 + 2048);
+
+        let (_, decrypt_identity) = generate_identity("bob", Some(bob_private_key));
+        let decrypt_identity: &'static EncItIdentity = Box::leak(decrypt_identity);
+        let (_, decrypt_friend) = generate_friend("alice", Some(alice_private_key));
+        let decrypt_friend: &'static EncItFriend = Box::leak(decrypt_friend);
+        let mut decrypt_cfg_mock = MockEncItConfig::new();
+        decrypt_cfg_mock
+            .expect_identity_by_key_id()
+            .returning(move |_| Some(decrypt_identity));
+        decrypt_cfg_mock
+            .expect_friend_by_key_id()
+            .returning(move |_| Some(decrypt_friend));
+        let message = EncItImpl::new(Rc::new(decrypt_cfg_mock)).decrypt(
+            &enc_msg,
+            None,
+            &EncItDecryptOptions::default(),
+        )?;
+        assert_eq!(message.payload(), plain_message.as_slice());
+        assert!(message.verified());
+        Ok(())
+    }
+
+    #[test]
+    fn compressed_payload() -> Result<(), EncItError> {
+        let (bob_private_key, bob) = generate_friend("bob", None);
+        let bob: &'static EncItFriend = Box::leak(bob);
+        let mut encrypt_cfg_mock = MockEncItConfig::new();
+        encrypt_cfg_mock
+            .expect_friend()
+            .returning(move |_| Some(bob));
+        let plain_message = "2021-12-01 INFO request served in 3ms\n".repeat(1000);
+        let enc_msg = EncItImpl::new(Rc::new(encrypt_cfg_mock)).encrypt(
+            None,
+            &["bob", "bob"],
+            &EncItEncryptOptions {
+                compression: EncItCompression::Auto,
+                ..Default::default()
+            },
+            plain_message.as_bytes(),
+        )?;
+        assert!(enc_msg.len() < plain_message.len() / 10);
+
+        let (_, decrypt_identity) = generate_identity("bob", Some(bob_private_key));
+        let decrypt_identity: &'static EncItIdentity = Box::leak(decrypt_identity);
+        let mut decrypt_cfg_mock = MockEncItConfig::new();
+        decrypt_cfg_mock
+            .expect_identity_by_key_id()
+            .returning(move |_| Some(decrypt_identity));
+        let message = EncItImpl::new(Rc::new(decrypt_cfg_mock)).decrypt(
+            &enc_msg,
+            None,
+            &EncItDecryptOptions::default(),
+        )?;
+        assert_eq!(message.payload(), plain_message.as_bytes());
+        Ok(())
+    }
+
+    #[test]
+    fn encrypt_decrypt_password() -> Result<(), EncItError> {
+        let (alice_private_key, alice) =
+            generate_identity("alice", Some(EncItKeyType::EcP256.generate()?));
+        let alice: &'static EncItIdentity = Box::leak(alice);
+        let mut encrypt_cfg_mock = MockEncItConfig::new();
+        encrypt_cfg_mock
+            .expect_identity()
+            .returning(move |_| Some(alice));
+        let password = EncItPassword::new("correct horse battery staple".to_string());
+        let enc_msg = EncItImpl::new(Rc::new(encrypt_cfg_mock)).encrypt(
+            Some("alice"),
+            &[],
+            &EncItEncryptOptions {
+                subject: Some("invitation".to_string()),
+                password: Some(password.clone()),
+                password_iterations: Some(PBES2_MIN_ITERATIONS),
+                ..Default::default()
+            },
+            b"hello",
+        )?;
+        assert!(is_password_encrypted(&enc_msg));
+        assert_eq!(
+            jwt::decode_header(&enc_msg)?.claim("p2c"),
+            Some(&Value::from(PBES2_MIN_ITERATIONS))
+        );
+
+        let (_, decrypt_friend) = generate_friend("alice", Some(alice_private_key));
+        let decrypt_friend: &'static EncItFriend = Box::leak(decrypt_friend);
+        let mut decrypt_cfg_mock = MockEncItConfig::new();
+        decrypt_cfg_mock
+            .expect_friend_by_key_id()
+            .returning(move |_| Some(decrypt_friend));
+        let enc_it = EncItImpl::new(Rc::new(decrypt_cfg_mock));
+        assert!(matches!(
+            enc_it.decrypt(&enc_msg, None, &EncItDecryptOptions::default()),
+            Err(EncItError::InvalidCommand(_))
+        ));
+        assert!(matches!(
+            enc_it.decrypt(
+                &enc_msg,
+                None,
+                &EncItDecryptOptions {
+                    password: Some(EncItPassword::new("wrong".to_string())),
+                    ..Default::default()
+                }
+            ),
+            Err(EncItError::InvalidPassword())
+        ));
+        let message = enc_it.decrypt(
+            &enc_msg,
+            None,
+            &EncItDecryptOptions {
+                password: Some(password),
+                ..Default::default()
+            },
+        )?;
+        assert_eq!(message.payload(), b"hello");
+        assert_eq!(message.subject(), &Some("invitation".to_string()));
+        assert_eq!(message.sender(), Some("alice"));
+        assert!(message.verified());
+        Ok(())
+    }
+
+    #[test]
+    fn rewrapped_message() -> Result<(), EncItError> {
+        let (alice_private_key, alice) =
+            generate_identity("alice", Some(EncItKeyType::Ed25519.generate()?));
+        let alice: &'static EncItIdentity = Box::leak(alice);
+        let (bob_private_key, bob) = generate_friend("bob", None);
+        let bob: &'static EncItFriend = Box::leak(bob);
+        let (carol_private_key, carol) =
+            generate_friend("carol", Some(EncItKeyType::EcP256.generate()?));
+        let mut encrypt_cfg_mock = MockEncItConfig::new();
+        encrypt_cfg_mock
+            .expect_identity()
+            .returning(move |_| Some(alice));
+        encrypt_cfg_mock
+            .expect_friend()
+            .returning(move |_| Some(bob));
+        let enc_msg = EncItImpl::new(Rc::new(encrypt_cfg_mock)).encrypt(
+            Some("alice"),
+            &["bob"],
+            &EncItEncryptOptions {
+                subject: Some("for bob".to_string()),
+                ..Default::default()
+            },
+            b"hello bob",
+        )?;
+
+        // bob decrypts the outer layer and re-encrypts the signed plaintext
+        let (_, bob_identity) = generate_identity("bob", Some(bob_private_key));
+        let (plaintext, _) = EncItImpl::extract_jwe(&enc_msg, &bob_identity)?;
+        let to_carol = EncItImpl::create_jwe(&plaintext, &[&carol], false, false)?;
+
+        let (_, decrypt_friend) = generate_friend("alice", Some(alice_private_key));
+        let decrypt_friend: &'static EncItFriend = Box::leak(decrypt_friend);
+        let bob_identity: &'static EncItIdentity = Box::leak(bob_identity);
+        let (_, carol_identity) = generate_identity("carol", Some(carol_private_key));
+        let carol_identity: &'static EncItIdentity = Box::leak(carol_identity);
+        let mut decrypt_cfg_mock = MockEncItConfig::new();
+        decrypt_cfg_mock.expect_identity().returning(move |name| {
+            [bob_identity, carol_identity]
+                .into_iter()
+                .find(|i| i.name() == name)
+        });
+        decrypt_cfg_mock
+            .expect_friend_by_key_id()
+            .returning(move |_| Some(decrypt_friend));
+        let enc_it = EncItImpl::new(Rc::new(decrypt_cfg_mock));
+        let options = EncItDecryptOptions::default();
+        assert_eq!(
+            enc_it.decrypt(&enc_msg, Some("bob"), &options)?.payload(),
+            b"hello bob"
+        );
+        assert!(matches!(
+            enc_it.decrypt(&to_carol, Some("carol"), &options),
+            Err(EncItError::InvalidRecipient())
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn forwarded_message() -> Result<(), EncItError> {
+        let (alice_private_key, alice) =
+            generate_identity("alice", Some(EncItKeyType::Ed25519.generate()?));
+        let alice: &'static EncItIdentity = Box::leak(alice);
+        let (bob_private_key, bob) = generate_friend("bob", None);
+        let bob: &'static EncItFriend = Box::leak(bob);
+        let (carol_private_key, carol) =
+            generate_friend("carol", Some(EncItKeyType::EcP256.generate()?));
+        let carol: &'static EncItFriend = Box::leak(carol);
+        let mut encrypt_cfg_mock = MockEncItConfig::new();
+        encrypt_cfg_mock
+            .expect_identity()
+            .returning(move |_| Some(alice));
+        encrypt_cfg_mock
+            .expect_friend()
+            .returning(move |_| Some(bob));
+        let enc_msg = EncItImpl::new(Rc::new(encrypt_cfg_mock)).encrypt(
+            Some("alice"),
+            &["bob"],
+            &EncItEncryptOptions {
+                subject: Some("for bob".to_string()),
+                ..Default::default()
+            },
+            b"hello bob",
+        )?;
+
+        // bob forwards the message to carol
+        let (_, alice_friend) = generate_friend("alice", Some(alice_private_key));
+        let alice_friend: &'static EncItFriend = Box::leak(alice_friend);
+        let (_, bob_identity) = generate_identity("bob", Some(bob_private_key.clone()));
+        let bob_identity: &'static EncItIdentity = Box::leak(bob_identity);
+        let mut forward_cfg_mock = MockEncItConfig::new();
+        forward_cfg_mock
+            .expect_identity()
+            .returning(move |_| Some(bob_identity));
+        forward_cfg_mock
+            .expect_friend()
+            .returning(move |name| Some(carol).filter(|carol| carol.name() == name));
+        forward_cfg_mock
+            .expect_friend_by_key_id()
+            .returning(move |_| Some(alice_friend));
+        let forward_enc_it = EncItImpl::new(Rc::new(forward_cfg_mock));
+        let options = EncItDecryptOptions::default();
+        let to_carol = forward_enc_it.forward(&enc_msg, "bob", &["carol"], &options)?;
+        assert!(matches!(
+            forward_enc_it.forward(&enc_msg, "bob", &["dave"], &options),
+            Err(EncItError::FriendNotFound(_))
+        ));
+
+        // carol verifies alice's signature and bob's forward
+        let (_, bob_friend) = generate_friend("bob", Some(bob_private_key));
+        let bob_friend: &'static EncItFriend = Box::leak(bob_friend);
+        let (_, carol_identity) = generate_identity("carol", Some(carol_private_key));
+        let carol_identity: &'static EncItIdentity = Box::leak(carol_identity);
+        let alice_sha = alice_friend.public_key().thumbprint()?;
+        let bob_sha = bob_friend.public_key().thumbprint()?;
+        let bob_sha2 = bob_sha.clone();
+        let bob_sha3 = bob_sha.clone();
+        let mut decrypt_cfg_mock = MockEncItConfig::new();
+        decrypt_cfg_mock
+            .expect_identity()
+            .returning(move |_| Some(carol_identity));
+        decrypt_cfg_mock
+            .expect_friend_by_key_id()
+            .returning(move |sha| {
+                if sha == alice_sha {
+                    Some(alice_friend)
+                } else if sha == bob_sha2 {
+                    Some(bob_friend)
+                } else {
+                    None
+                }
+            });
+        let decrypted = EncItImpl::new(Rc::new(decrypt_cfg_mock)).decrypt(
+            &to_carol,
+            Some("carol"),
+            &options,
+        )?;
+        assert_eq!(decrypted.payload(), b"hello bob");
+        assert_eq!(decrypted.subject(), &Some("for bob".to_string()));
+        assert_eq!(decrypted.sender(), Some("alice"));
+        assert!(decrypted.verified());
+        assert_eq!(decrypted.forwarded_by(), Some("bob"));
+        assert_eq!(decrypted.forwarder_fingerprint(), Some(bob_sha3.as_str()));
+
+        // the forwarder is not a friend of carol
+        let mut unknown_cfg_mock = MockEncItConfig::new();
+        unknown_cfg_mock
+            .expect_identity()
+            .returning(move |_| Some(carol_identity));
+        unknown_cfg_mock
+            .expect_friend_by_key_id()
+            .returning(move |sha| Some(alice_friend).filter(|_| sha != bob_sha));
+        let enc_it = EncItImpl::new(Rc::new(unknown_cfg_mock));
+        assert!(matches!(
+            enc_it.decrypt(&to_carol, Some("carol"), &options),
+            Err(EncItError::FriendNotFound(_))
+        ));
+        let decrypted = enc_it.decrypt(
+            &to_carol,
+            Some("carol"),
+            &EncItDecryptOptions {
+                allow_unknown_sender: true,
+                ..Default::default()
+            },
+        )?;
+        // anyone can sign a forward with the key they embed, it does not vouch for the
+        // original recipient check that was skipped
+        assert!(decrypted.signed());
+        assert!(!decrypted.verified());
+        assert_eq!(decrypted.sender(), Some("alice"));
+        assert_eq!(decrypted.forwarded_by(), None);
+        assert!(decrypted.forwarder_fingerprint().is_some());
+
+        // a recipient of alice's message re-encrypts it to carol with a forged forward
+        let carol_audience = vec![carol.public_key().thumbprint()?];
+        let (payload, _) = EncItImpl::extract_jwe(&to_carol, carol_identity)?;
+        let (envelope, message) = EncItImpl::split_binary_payload(&payload)?;
+        let forged_signature = |header: Value| -> Result<String, EncItError> {
+            Ok(format!(
+                "{}..{}",
+                base64::encode_config(serde_json::to_vec(&header)?, base64::URL_SAFE_NO_PAD),
+                base64::encode_config([0u8; 64], base64::URL_SAFE_NO_PAD)
+            ))
+        };
+        // alice's key does not match the forged key id
+        let alice_jwk = Value::Object(
+            crate::config::public_key_to_jwk(&alice_friend.public_key().public_key()?)?
+                .as_ref()
+                .clone(),
+        );
+        let forged_headers = [
+            serde_json::json!({
+                "alg": "EdDSA", "b64": false, "crit": ["b64"],
+                "kid": "forged", "aud": carol_audience,
+            }),
+            serde_json::json!({
+                "alg": "EdDSA", "b64": false, "crit": ["b64"],
+                "kid": "forged", "aud": carol_audience,
+                "jwk": alice_jwk,
+            }),
+        ];
+        for forged_header in forged_headers {
+            let forged_envelope = EncItEnvelope {
+                jws: envelope.jws.clone(),
+                claims: None,
+                forwarded: Some(forged_signature(forged_header)?),
+            };
+            let forged = EncItImpl::create_jwe(
+                &EncItImpl::create_binary_payload(&forged_envelope, message)?,
+                &[carol],
+                false,
+                false,
+            )?;
+            assert!(enc_it
+                .decrypt(
+                    &forged,
+                    Some("carol"),
+                    &EncItDecryptOptions {
+                        allow_unknown_sender: true,
+                        ..Default::default()
+                    },
+                )
+                .is_err());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn hidden_recipients() -> Result<(), EncItError> {
+        let (alice_private_key, alice) = generate_identity("alice", None);
+        let alice: &'static EncItIdentity = Box::leak(alice);
+        let (bob_private_key, bob) = generate_friend("bob", None);
+        let bob: &'static EncItFriend = Box::leak(bob);
+        let (carol_private_key, carol) =
+            generate_friend("carol", Some(EncItKeyType::EcP256.generate()?));
+        let carol: &'static EncItFriend = Box::leak(carol);
+        let mut encrypt_cfg_mock = MockEncItConfig::new();
+        encrypt_cfg_mock
+            .expect_identity()
+            .returning(move |_| Some(alice));
+        encrypt_cfg_mock
+            .expect_friend()
+            .returning(move |f| [bob, carol].into_iter().find(|c| c.name() == f));
+        let enc_it = EncItImpl::new(Rc::new(encrypt_cfg_mock));
+        let options = EncItEncryptOptions {
+            hide_recipients: true,
+            ..Default::default()
+        };
+        let to_bob = enc_it.encrypt(Some("alice"), &["bob"], &options, b"hello")?;
+        let to_both = enc_it.encrypt(Some("alice"), &["bob", "carol"], &options, b"hello")?;
+        assert!(EncItImpl::recipients_key_ids(&to_bob)?.is_empty());
+        assert!(EncItImpl::recipients_key_ids(&to_both)?.is_empty());
+
+        // the first identity of each config cannot decrypt the message
+        let (_, dave) = generate_identity("dave", None);
+        let (_, bob_identity) = generate_identity("bob", Some(bob_private_key));
+        let (_, carol_identity) = generate_identity("carol", Some(carol_private_key));
+        let (_, decrypt_friend) = generate_friend("alice", Some(alice_private_key));
+        let decrypt_friend: &'static EncItFriend = Box::leak(decrypt_friend);
+        for (identities, enc_msg) in [
+            (vec![*dave.clone(), *bob_identity.clone()], &to_bob),
+            (vec![*dave.clone(), *bob_identity], &to_both),
+            (vec![*dave, *carol_identity], &to_both),
+        ] {
+            let receiver = identities[1].name().to_string();
+            let mut decrypt_cfg_mock = MockEncItConfig::new();
+            decrypt_cfg_mock
+                .expect_identities()
+                .return_const(identities);
+            decrypt_cfg_mock
+                .expect_friend_by_key_id()
+                .returning(move |_| Some(decrypt_friend));
+            let message = EncItImpl::new(Rc::new(decrypt_cfg_mock)).decrypt(
+                enc_msg,
+                None,
+                &EncItDecryptOptions::default(),
+            )?;
+            assert_eq!(message.payload(), b"hello");
+            assert_eq!(message.receiver(), receiver);
+            assert!(message.verified());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn decrypt_legacy_payload() -> Result<(), EncItError> {
+        let (alice_private_key, alice) = generate_identity("alice", None);
+        let (bob_private_key, bob) = generate_friend("bob", None);
+        // format used before the binary plaintext: the base64 message is a JWT claim
+        let content =
+            EncItImpl::create_content(&base64::encode("hello"), &EncItEncryptOptions::default())?;
+        let mut payload = JwtPayload::new();
+        payload.set_issuer(alice.private_key().public_key_pem_sha()?);
+        payload.set_claim(
+            "message",
+            Some(Value::String(EncItImpl::create_jws(
+                &content, &alice, false,
+            )?)),
+        )?;
+        let mut jwe_header = JweHeader::new();
+        jwe_header.set_token_type("JWT");
+        jwe_header.set_content_encryption("A128CBC-HS256");
+        jwe_header.set_subject("legacy subject");
+        jwe_header.set_claim("rcp", Some(bob.public_key().sha_pem()?.into()))?;
+        let legacy_msg = jwt::encode_with_encrypter(
+            &payload,
+            &jwe_header,
+            &*EncItImpl::encrypter(bob.public_key())?,
+        )?;
+
+        let (_, decrypt_identity) = generate_identity("bob", Some(bob_private_key));
+        let decrypt_identity: &'static EncItIdentity = Box::leak(decrypt_identity);
+        let (_, decrypt_friend) = generate_friend("alice", Some(alice_private_key));
+        let decrypt_friend: &'static EncItFriend = Box::leak(decrypt_friend);
+        let mut decrypt_cfg_mock = MockEncItConfig::new();
+        decrypt_cfg_mock
+            .expect_identity_by_key_id()
+            .returning(move |_| Some(decrypt_identity));
+        decrypt_cfg_mock
+            .expect_friend_by_key_id()
+            .returning(move |_| Some(decrypt_friend));
+        let message = EncItImpl::new(Rc::new(decrypt_cfg_mock)).decrypt(
+            &legacy_msg,
+            None,
+            &EncItDecryptOptions::default(),
+        )?;
+        assert_eq!(message.payload(), b"hello");
+        assert_eq!(message.subject(), &Some("legacy subject".to_string()));
+        assert_eq!(message.sender(), Some("alice"));
+        assert!(message.verified());
+        Ok(())
+    }
+
+    #[test]
+    fn encrypt_decrypt_multiple_friends() -> Result<(), EncItError> {
+        // mixed key types: alice signs with ES384, bob has a RSA key, carol a P-256 one
+        let encrypt_identity_name = "alice";
+        let (encrypt_identity_private_key, encrypt_identity) = generate_identity(
+            encrypt_identity_name,
+            Some(EncItKeyType::EcP384.generate()?),
+        );
+        let (_, bob) = generate_friend("bob", None);
+        let (carol_private_key, carol) =
+            generate_friend("carol", Some(EncItKeyType::EcP256.generate()?));
+        let encrypt_identity = Box::leak(encrypt_identity);
+        let bob: &'static EncItFriend = Box::leak(bob);
+        let carol: &'static EncItFriend = Box::leak(carol);
+        let carol_public_key_sha = Box::leak(Box::new(carol.public_key().thumbprint()?));
+        let encrypt_identity_public_key_sha = Box::leak(Box::new(
+            encrypt_identity.private_key().public_key_thumbprint()?,
+        ));
+        let mut encrypt_cfg_mock = MockEncItConfig::new();
+        encrypt_cfg_mock
+            .expect_friend()
+            .returning(move |f| [bob, carol].into_iter().find(|c| c.name() == f));
+        encrypt_cfg_mock
+            .expect_identity()
+            .with(eq(encrypt_identity_name))
+            .returning(|_| Some(encrypt_identity));
+        let enc_it = EncItImpl::new(Rc::new(encrypt_cfg_mock));
+        let enc_msg = enc_it.encrypt(
+            Some(encrypt_identity_name),
+            &["bob", "carol"],
+            &EncItEncryptOptions::default(),
+            b"hello",
+        )?;
+
+        let recipients = EncItImpl::recipients_key_ids(&enc_msg)?;
+        assert_eq!(recipients.len(), 2);
+
+        // decrypt as carol
+        let (_, decrypt_friend) =
+            generate_friend(encrypt_identity_name, Some(encrypt_identity_private_key));
+        let (_, decrypt_identity) = generate_identity("carol", Some(carol_private_key));
+        let decrypt_friend = Box::leak(decrypt_friend);
+        let decrypt_identity: &'static EncItIdentity = Box::leak(decrypt_identity);
+        let mut decrypt_cfg_mock = MockEncItConfig::new();
+        decrypt_cfg_mock
+            .expect_identity_by_key_id()
+            .returning(move |sha| {
+                Some(decrypt_identity).filter(|_| sha == carol_public_key_sha.as_str())
+            });
+        decrypt_cfg_mock
+            .expect_friend_by_key_id()
+            .with(eq(encrypt_identity_public_key_sha.as_str()))
+            .returning(|_| Some(decrypt_friend));
+        let enc_it = EncItImpl::new(Rc::new(decrypt_cfg_mock));
+
+        let message = enc_it.decrypt(&enc_msg, None, &EncItDecryptOptions::default())?;
+        assert_eq!(message.payload, b"hello");
+        assert!(message.verified);
+        assert_eq!(message.sender(), Some(encrypt_identity_name));
+        assert_eq!(message.receiver, "carol");
+        Ok(())
+    }
+
+    #[test]
+    fn encrypt_decrypt_anonymous() -> Result<(), EncItError> {
+        let (bob_private_key, bob) = generate_friend("bob", None);
+        let bob: &'static EncItFriend = Box::leak(bob);
+        let mut encrypt_cfg_mock = MockEncItConfig::new();
+        encrypt_cfg_mock
+            .expect_friend()
+            .with(eq("bob"))
+            .returning(move |_| Some(bob));
+        let enc_it = EncItImpl::new(Rc::new(encrypt_cfg_mock));
+        let enc_msg = enc_it.encrypt(
+            None,
+            &["bob"],
+            &EncItEncryptOptions {
+                subject: None,
+                expires_in: Some(Duration::ZERO),
+                ..Default::default()
+            },
+            b"drop-box",
+        )?;
+
+        let (_, decrypt_identity) = generate_identity("bob", Some(bob_private_key));
+        let decrypt_identity: &'static EncItIdentity = Box::leak(decrypt_identity);
+        let mut decrypt_cfg_mock = MockEncItConfig::new();
+        decrypt_cfg_mock
+            .expect_identity_by_key_id()
+            .returning(move |_| Some(decrypt_identity));
+        let enc_it = EncItImpl::new(Rc::new(decrypt_cfg_mock));
+
+        assert!(matches!(
+            enc_it.decrypt(&enc_msg, None, &EncItDecryptOptions::default()),
+            Err(EncItError::MessageExpired(_))
+        ));
+        let message = enc_it.decrypt(
+            &enc_msg,
+            None,
+            &EncItDecryptOptions {
+                allow_expired: true,
+                ..Default::default()
+            },
+        )?;
+        assert_eq!(message.payload(), b"drop-box");
+        assert_eq!(message.sender(), None);
+        assert!(!message.signed());
+        assert!(!message.verified());
+        assert_eq!(message.receiver(), "bob");
+        Ok(())
+    }
+
+    #[test]
+    fn replayed_message() -> Result<(), EncItError> {
+        let (bob_private_key, bob) = generate_friend("bob", None);
+        let bob: &'static EncItFriend = Box::leak(bob);
+        let mut encrypt_cfg_mock = MockEncItConfig::new();
+        encrypt_cfg_mock
+            .expect_friend()
+            .with(eq("bob"))
+            .returning(move |_| Some(bob));
+        let enc_it = EncItImpl::new(Rc::new(encrypt_cfg_mock));
+        let message = enc_it.encrypt(None, &["bob"], &EncItEncryptOptions::default(), b"once")?;
+        let burn_message = enc_it.encrypt(
+            None,
+            &["bob"],
+            &EncItEncryptOptions {
+                burn_after_reading: true,
+                ..Default::default()
+            },
+            b"only once",
+        )?;
+        let stream_header = enc_it.encrypt(
+            None,
+            &["bob"],
+            &EncItEncryptOptions {
+                burn_after_reading: true,
+                ..Default::default()
+            },
+            b"stream header",
+        )?;
+
+        let (_, decrypt_identity) = generate_identity("bob", Some(bob_private_key));
+        let decrypt_identity: &'static EncItIdentity = Box::leak(decrypt_identity);
+        let mut decrypt_cfg_mock = MockEncItConfig::new();
+        decrypt_cfg_mock
+            .expect_identity_by_key_id()
+            .returning(move |_| Some(decrypt_identity));
+        let seen_ids: Arc<Mutex<Vec<String>>> = Arc::new(Mutex::new(Vec::new()));
+        let mut seen_messages_mock = MockEncItSeenMessages::new();
+        let seen_ids_clone = seen_ids.clone();
+        seen_messages_mock
+            .expect_seen()
+            .returning(move |id| Ok(seen_ids_clone.lock().unwrap().iter().any(|seen| seen == id)));
+        let seen_ids_clone = seen_ids.clone();
+        seen_messages_mock.expect_add().returning(move |id| {
+            seen_ids_clone.lock().unwrap().push(id.to_string());
+            Ok(())
+        });
+        let enc_it = EncItImpl::new(Rc::new(decrypt_cfg_mock))
+            .with_seen_messages(Rc::new(seen_messages_mock));
+        let options = EncItDecryptOptions::default();
+
+        let first = enc_it.decrypt(&message, None, &options)?;
+        assert!(!first.replayed());
+        assert_eq!(first.id().map(|id| id.len()), Some(32));
+        assert!(enc_it.decrypt(&message, None, &options)?.replayed());
+
+        let first = enc_it.decrypt(&burn_message, None, &options)?;
+        assert!(first.burn_after_reading());
+        assert!(matches!(
+            enc_it.decrypt(&burn_message, None, &options),
+            Err(EncItError::MessageAlreadyRead(_))
+        ));
+        assert_eq!(seen_ids.lock().unwrap().len(), 2);
+
+        // an incomplete stream does not burn the message id
+        let deferred_options = EncItDecryptOptions {
+            defer_seen: true,
+            ..Default::default()
+        };
+        enc_it.decrypt(&stream_header, None, &deferred_options)?;
+        let header_message = enc_it.decrypt(&stream_header, None, &deferred_options)?;
+        assert_eq!(seen_ids.lock().unwrap().len(), 2);
+        enc_it.mark_seen(&header_message)?;
+        enc_it.mark_seen(&header_message)?;
+        assert_eq!(seen_ids.lock().unwrap().len(), 3);
+        assert!(matches!(
+            enc_it.decrypt(&stream_header, None, &deferred_options),
+            Err(EncItError::MessageAlreadyRead(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn decrypt_unknown_sender() -> Result<(), EncItError> {
+        let (alice_private_key, alice) =
+            generate_identity("alice", Some(EncItKeyType::Ed25519.generate()?));
+        let alice: &'static EncItIdentity = Box::leak(alice);
+        let alice_sha = alice.private_key().public_key_thumbprint()?;
+        let (bob_private_key, bob) = generate_friend("bob", None);
+        let bob: &'static EncItFriend = Box::leak(bob);
+        let mut encrypt_cfg_mock = MockEncItConfig::new();
+        encrypt_cfg_mock
+            .expect_identity()
+            .with(eq("alice"))
+            .returning(move |_| Some(alice));
+        encrypt_cfg_mock
+            .expect_friend()
+            .with(eq("bob"))
+            .returning(move |_| Some(bob));
+        let enc_it = EncItImpl::new(Rc::new(encrypt_cfg_mock));
+        let embedded_key_msg = enc_it.encrypt(
+            Some("alice"),
+            &["bob"],
+            &EncItEncryptOptions {
+                embed_key: true,
+                ..Default::default()
+            },
+            b"hello",
+        )?;
+        let msg = enc_it.encrypt(
+            Some("alice"),
+            &["bob"],
+            &EncItEncryptOptions::default(),
+            b"hello",
+        )?;
+
+        let (_, decrypt_identity) = generate_identity("bob", Some(bob_private_key));
+        let decrypt_identity: &'static EncItIdentity = Box::leak(decrypt_identity);
+        let mut decrypt_cfg_mock = MockEncItConfig::new();
+        decrypt_cfg_mock
+            .expect_identity_by_key_id()
+            .returning(move |_| Some(decrypt_identity));
+        decrypt_cfg_mock
+            .expect_friend_by_key_id()
+            .returning(|_| None);
+        let enc_it = EncItImpl::new(Rc::new(decrypt_cfg_mock));
+
+        assert!(matches!(
+            enc_it.decrypt(&msg, None, &EncItDecryptOptions::default()),
+            Err(EncItError::FriendNotFound(_))
+        ));
+        let options = EncItDecryptOptions {
+            allow_unknown_sender: true,
+            ..Default::default()
+        };
+        // without the embedded key the signature cannot be checked
+        for (enc_msg, signed) in [(&msg, false), (&embedded_key_msg, true)] {
+            let message = enc_it.decrypt(enc_msg, None, &options)?;
+            assert_eq!(message.payload(), b"hello");
+            assert_eq!(message.sender(), None);
+            assert_eq!(message.signed(), signed);
+            assert!(!message.verified());
+            assert_eq!(message.sender_fingerprint(), Some(alice_sha.as_str()));
+        }
+
+        let sender_key = enc_it.sender_key(&embedded_key_msg, None)?;
+        assert_eq!(sender_key.pem()?, alice_private_key.public_key_to_pem()?);
+        assert!(matches!(
+            enc_it.sender_key(&msg, None),
+            Err(EncItError::DecodeError(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn sign_verify() -> Result<(), EncItError> {
+        for key_type in [
+            EncItKeyType::Rsa,
+            EncItKeyType::EcP256,
+            EncItKeyType::Ed25519,
+        ] {
+            let (identity_private_key, identity) =
+                generate_identity("alice", Some(key_type.generate()?));
+            let identity: &'static EncItIdentity = Box::leak(identity);
+            let mut sign_cfg_mock = MockEncItConfig::new();
+            sign_cfg_mock
+                .expect_identity()
+                .with(eq("alice"))
+                .returning(move |_| Some(identity));
+            let message = b"release.tar.gz content";
+            let signature = EncItImpl::new(Rc::new(sign_cfg_mock)).sign("alice", message)?;
+            assert_eq!(signature.split('.').nth(1), Some(""));
+
+            let (_, friend) = generate_friend("alice", Some(identity_private_key));
+            let friend: &'static EncItFriend = Box::leak(friend);
+            let mut verify_cfg_mock = MockEncItConfig::new();
+            verify_cfg_mock
+                .expect_friend_by_key_id()
+                .returning(move |_| Some(friend));
+            let enc_it = EncItImpl::new(Rc::new(verify_cfg_mock));
+            assert_eq!(enc_it.verify(&signature, message)?, "alice");
+            assert!(matches!(
+                enc_it.verify(&signature, b"tampered content"),
+                Err(EncItError::InvalidSignature())
+            ));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn key_transition() -> Result<(), EncItError> {
+        let (alice_private_key, alice) =
+            generate_identity("alice", Some(EncItKeyType::Ed25519.generate()?));
+        let alice: &'static EncItIdentity = Box::leak(alice);
+        let mut alice_cfg_mock = MockEncItConfig::new();
+        alice_cfg_mock
+            .expect_identity()
+            .with(eq("alice"))
+            .returning(move |_| Some(alice));
+        let new_private_key = EncItKeyType::EcP256.generate()?;
+        let new_key = EncItPrivateKey::new(
+            EncItPEM::Base64(base64::encode(EncItKeyType::private_key_to_pem(
+                &new_private_key,
+            )?)),
+            None,
+        );
+        let statement = EncItImpl::new(Rc::new(alice_cfg_mock)).transition("alice", &new_key)?;
+
+        let old_key_id = alice.private_key().public_key_thumbprint()?;
+        let (_, friend) = generate_friend("alice", Some(alice_private_key));
+        let friend: &'static EncItFriend = Box::leak(friend);
+        let mut bob_cfg_mock = MockEncItConfig::new();
+        bob_cfg_mock
+            .expect_friend_by_key_id()
+            .returning(move |key_id| Some(friend).filter(|_| key_id == old_key_id));
+        let enc_it = EncItImpl::new(Rc::new(bob_cfg_mock));
+        let (friend_name, friend_new_key) = enc_it.verify_transition(&statement)?;
+        assert_eq!(friend_name, "alice");
+        assert_eq!(
+            friend_new_key.thumbprint()?,
+            new_key.public_key_thumbprint()?
+        );
+
+        // the statement has to be signed by both keys
+        for index in 0..2 {
+            let mut partial: Map<String, Value> = serde_json::from_str(&statement)?;
+            partial["signatures"].as_array_mut().unwrap().remove(index);
+            assert!(matches!(
+                enc_it.verify_transition(&serde_json::to_string(&partial)?),
+                Err(EncItError::InvalidSignature())
+            ));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn decrypt_with_previous_key() -> Result<(), EncItError> {
+        let (bob_private_key, bob_friend) = generate_friend("bob", None);
+        let bob_friend: &'static EncItFriend = Box::leak(bob_friend);
+        let mut alice_cfg_mock = MockEncItConfig::new();
+        alice_cfg_mock
+            .expect_friend()
+            .with(eq("bob"))
+            .returning(move |_| Some(bob_friend));
+        let enc_msg = EncItImpl::new(Rc::new(alice_cfg_mock)).encrypt(
+            None,
+            &["bob"],
+            &EncItEncryptOptions::default(),
+            b"hello",
+        )?;
+
+        // bob rotated his key after the message was sent
+        let (_, old_bob) = generate_identity("bob", Some(bob_private_key));
+        let (_, new_bob) = generate_identity("bob", Some(EncItKeyType::Ed25519.generate()?));
+        let mut bob = serde_json::to_value(&*new_bob)?;
+        bob["previousKeys"] = serde_json::json!([old_bob.private_key()]);
+        let bob: &'static EncItIdentity = Box::leak(Box::new(serde_json::from_value(bob)?));
+        let old_key_id = old_bob.private_key().public_key_thumbprint()?;
+        let mut bob_cfg_mock = MockEncItConfig::new();
+        bob_cfg_mock
+            .expect_identity_by_key_id()
+            .returning(move |key_id| Some(bob).filter(|_| key_id == old_key_id));
+        let message = EncItImpl::new(Rc::new(bob_cfg_mock)).decrypt(
+            &enc_msg,
+            None,
+            &EncItDecryptOptions::default(),
+        )?;
+        assert_eq!(message.payload, b"hello");
+        assert_eq!(message.receiver, "bob");
+        Ok(())
+    }
+
+    #[test]
+    fn clear_sign_verify() -> Result<(), EncItError> {
+        let (identity_private_key, identity) =
+            generate_identity("alice", Some(EncItKeyType::EcP256.generate()?));
+        let identity: &'static EncItIdentity = Box::leak(identity);
+        let (_, friend) = generate_friend("alice", Some(identity_private_key));
+        let friend: &'static EncItFriend = Box::leak(friend);
+        let mut cfg_mock = MockEncItConfig::new();
+        cfg_mock
+            .expect_identity()
+            .with(eq("alice"))
+            .returning(move |_| Some(identity));
+        cfg_mock
+            .expect_friend_by_key_id()
+            .returning(move |_| Some(friend));
+        let enc_it = EncItImpl::new(Rc::new(cfg_mock));
+
+        let message = "release 1.0 is out\n-----BEGIN ENCIT SIGNATURE-----\n";
+        let clear_signed = enc_it.clear_sign("alice", message)?;
+        assert!(clear_signed.contains(message));
+        let decrypted = enc_it.decrypt(
+            &format!("Hi all,\n\n{}", clear_signed),
+            None,
+            &EncItDecryptOptions::default(),
+        )?;
+        assert_eq!(decrypted.sender(), Some("alice"));
+        assert!(decrypted.verified());
+        assert_eq!(decrypted.payload(), message.as_bytes());
+
+        let tampered = clear_signed.replace("release 1.0", "release 6.6");
+        assert!(matches!(
+            enc_it.decrypt(&tampered, None, &EncItDecryptOptions::default()),
+            Err(EncItError::InvalidSignature())
+        ));
         Ok(())
     }
 
     pub fn generate_friend(
         friend_name: &str,
-        key: Option<Rsa<Private>>,
-    ) -> (Rsa<Private>, Box<EncItFriend>) {
-        let friend_priv_key = key.unwrap_or_else(|| Rsa::generate(2048).unwrap());
+        key: Option<PKey<Private>>,
+    ) -> (PKey<Private>, Box<EncItFriend>) {
+        let friend_priv_key = key.unwrap_or_else(|| EncItKeyType::Rsa.generate().unwrap());
         let friend_pub_key = friend_priv_key.public_key_to_pem().unwrap();
         let friend_pub_key_b64 = base64::encode(friend_pub_key);
         let friend = Box::new(EncItFriend::new(
@@ -308,10 +2792,10 @@ pub mod tests {
 
     pub fn generate_identity(
         identity_name: &str,
-        key: Option<Rsa<Private>>,
-    ) -> (Rsa<Private>, Box<EncItIdentity>) {
-        let identity_pair = key.unwrap_or_else(|| Rsa::generate(2048).unwrap());
-        let identity_pub_key = identity_pair.private_key_to_pem().unwrap();
+        key: Option<PKey<Private>>,
+    ) -> (PKey<Private>, Box<EncItIdentity>) {
+        let identity_pair = key.unwrap_or_else(|| EncItKeyType::Rsa.generate().unwrap());
+        let identity_pub_key = EncItKeyType::private_key_to_pem(&identity_pair).unwrap();
         let identity_pub_key_b64 = base64::encode(identity_pub_key);
         let identity_priv_key = EncItPrivateKey::new(EncItPEM::Base64(identity_pub_key_b64), None);
         let identity = Box::new(EncItIdentity::new(
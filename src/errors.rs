@@ -31,10 +31,28 @@ pub enum EncItError {
     JWTError(String),
     #[error("No message in payload claims")]
     EmptyMessage(),
+    #[error("The message expired at {0}")]
+    MessageExpired(String),
+    #[error("The message {0} has already been decrypted and it is burn after reading")]
+    MessageAlreadyRead(String),
+    #[error("Invalid signature")]
+    InvalidSignature(),
+    #[error("The message was not signed for this recipient")]
+    InvalidRecipient(),
+    #[error("Invalid password")]
+    InvalidPassword(),
     #[error("There is already a friend with that name")]
     FriendAlreadyExist(),
     #[error("There is already an identity with that name")]
     IdentityAlreadyExist(),
+    #[error("The fingerprint does not match the key of {0}")]
+    FingerprintMismatch(String),
+    #[error("The key of friend {0} has been revoked")]
+    FriendRevoked(String),
+    #[error("Invalid file name: {0}")]
+    InvalidFileName(String),
+    #[error("Stream Error: {0}")]
+    StreamError(String),
     #[error("Invalid command: {0}")]
     InvalidCommand(String),
     #[error("GenericError: {0}")]
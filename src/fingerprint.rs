@@ -0,0 +1,212 @@
+use crate::config::public_key_to_jwk;
+use crate::errors::EncItError;
+use openssl::pkey::{HasPublic, PKey};
+use openssl::sha::Sha256;
+
+/// bytes of the fingerprint shown grouped in hex, enough to compare a key by eye
+const GROUPED_FINGERPRINT_LEN: usize = 20;
+const HEX_GROUP_LEN: usize = 4;
+/// the numeric rendering is made of 6 groups of 5 digits, each group comes from 5 bytes
+const NUMERIC_GROUPS: usize = 6;
+const NUMERIC_GROUP_BYTES: usize = 5;
+const NUMERIC_GROUP_MODULUS: u64 = 100_000;
+const SAFETY_NUMBER_GROUPS_PER_LINE: usize = 4;
+
+/// RFC 7638 JWK thumbprint: SHA-256 of the required public JWK members in lexicographic
+/// order, it does not depend on how the key file was written (PEM line endings, PKCS#1)
+pub fn public_key_fingerprint<T: HasPublic>(key: &PKey<T>) -> Result<Vec<u8>, EncItError> {
+    let jwk = public_key_to_jwk(key)?;
+    let member = |name: &str| {
+        jwk.parameter(name)
+            .and_then(|value| value.as_str())
+            .ok_or_else(|| EncItError::EncodeError(format!("missing jwk parameter {}", name)))
+    };
+    let canonical_jwk = match jwk.key_type() {
+        "RSA" => format!(
+            r#"{{"e":"{}","kty":"RSA","n":"{}"}}"#,
+            member("e")?,
+            member("n")?
+        ),
+        "EC" => format!(
+            r#"{{"crv":"{}","kty":"EC","x":"{}","y":"{}"}}"#,
+            member("crv")?,
+            member("x")?,
+            member("y")?
+        ),
+        "OKP" => format!(
+            r#"{{"crv":"{}","kty":"OKP","x":"{}"}}"#,
+            member("crv")?,
+            member("x")?
+        ),
+        key_type => {
+            return Err(EncItError::EncodeError(format!(
+                "unsupported jwk {}",
+                key_type
+            )))
+        }
+    };
+    let mut sha = Sha256::new();
+    sha.update(canonical_jwk.as_bytes());
+    Ok(sha.finish().to_vec())
+}
+
+/// the base64url thumbprint used as key id (`kid`) in the messages
+pub fn key_id(fingerprint: &[u8]) -> String {
+    base64::encode_config(fingerprint, base64::URL_SAFE_NO_PAD)
+}
+
+/// the first 160 bits in upper case hex, in groups of 4 characters
+pub fn grouped_fingerprint(fingerprint: &[u8]) -> String {
+    let hex = hex::encode_upper(&fingerprint[..GROUPED_FINGERPRINT_LEN.min(fingerprint.len())]);
+    hex.as_bytes()
+        .chunks(HEX_GROUP_LEN)
+        .map(|group| String::from_utf8_lossy(group).into_owned())
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// 30 digits in groups of 5, easy to read aloud
+pub fn numeric_fingerprint(fingerprint: &[u8]) -> String {
+    numeric_groups(fingerprint).join(" ")
+}
+
+fn numeric_groups(fingerprint: &[u8]) -> Vec<String> {
+    fingerprint
+        .chunks_exact(NUMERIC_GROUP_BYTES)
+        .take(NUMERIC_GROUPS)
+        .map(|group| {
+            let value = group
+                .iter()
+                .fold(0u64, |value, byte| (value << 8) | *byte as u64);
+            format!("{:05}", value % NUMERIC_GROUP_MODULUS)
+        })
+        .collect()
+}
+
+/// The fingerprint read by the friend: the grouped hex (at least 160 bits), the numeric
+/// rendering or the base64url thumbprint, the spaces are ignored
+pub fn matches_fingerprint(fingerprint: &[u8], expected: &str) -> bool {
+    let expected: String = expected.split_whitespace().collect();
+    let numeric: String = numeric_groups(fingerprint).concat();
+    let hex = hex::encode(fingerprint);
+    if expected.len() == numeric.len() && expected.chars().all(|c| c.is_ascii_digit()) {
+        return expected == numeric;
+    }
+    if expected.len() >= GROUPED_FINGERPRINT_LEN * 2
+        && expected.len() <= hex.len()
+        && expected.chars().all(|c| c.is_ascii_hexdigit())
+    {
+        return hex.starts_with(&expected.to_ascii_lowercase());
+    }
+    expected == key_id(fingerprint)
+}
+
+/// The numeric fingerprints of both keys, the lower one first: both parties compute the same
+/// code whatever their own key is. 60 digits in 3 lines of 4 groups.
+pub fn safety_number(fingerprint: &[u8], other_fingerprint: &[u8]) -> String {
+    let mut fingerprints = [fingerprint, other_fingerprint];
+    fingerprints.sort();
+    let groups = fingerprints
+        .iter()
+        .flat_map(|fingerprint| numeric_groups(fingerprint))
+        .collect::<Vec<String>>();
+    groups
+        .chunks(SAFETY_NUMBER_GROUPS_PER_LINE)
+        .map(|line| line.join(" "))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EncItKeyType;
+
+    #[test]
+    fn renderings() {
+        let fingerprint = (0u8..32).collect::<Vec<u8>>();
+        assert_eq!(
+            grouped_fingerprint(&fingerprint),
+            "0001 0203 0405 0607 0809 0A0B 0C0D 0E0F 1011 1213"
+        );
+        // 0x0001020304 = 16909060, 0x0506070809 = 21575960585
+        let numeric = numeric_fingerprint(&fingerprint);
+        assert!(numeric.starts_with("09060 60585 "));
+        assert_eq!(numeric.len(), 6 * 5 + 5);
+    }
+
+    #[test]
+    fn rfc7638_thumbprint() -> Result<(), EncItError> {
+        // RFC 7638 section 3.1 example
+        let n = "0vx7agoebGcQSuuPiLJXZptN9nndrQmbXEps2aiAFbWhM78LhWx4cbbfAAtVT86zwu1RK7aPFFxuhDR1L6tSoc_BJECPebWKRXjBZCiFV4n3oknjhMstn64tZ_2W-5JsGY4Hc5n9yBXArwl93lqt7_RN5w6Cf0h4QyQ5v-65YGjQR0_FDW2QvzqY368QQMicAtaSqzs8KJZgnYb9c7d0zgdAZHzu6qMQvRL5hajrn1n91CbOpbISD08qNLyrdkt-bFTWhAI4vMQFh6WeZu0fM4lFd2NcRwr3XPksINHaQ-G_xBniIqbw0Ls1jF44-csFCur-kEgU8awapJzKnqDKgw";
+        let e = "AQAB";
+        let rsa = openssl::rsa::Rsa::from_public_components(
+            openssl::bn::BigNum::from_slice(&base64::decode_config(n, base64::URL_SAFE_NO_PAD)?)?,
+            openssl::bn::BigNum::from_slice(&base64::decode_config(e, base64::URL_SAFE_NO_PAD)?)?,
+        )?;
+        let key = PKey::from_rsa(rsa)?;
+        assert_eq!(
+            key_id(&public_key_fingerprint(&key)?),
+            "NzbLsXh8uDCcd-6MNwXF4W_7noWXFZAfHkxZsRGC9Xs"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn same_key_same_fingerprint() -> Result<(), EncItError> {
+        let key = EncItKeyType::EcP256.generate()?;
+        let public_key = PKey::public_key_from_pem(&key.public_key_to_pem()?)?;
+        assert_eq!(
+            public_key_fingerprint(&key)?,
+            public_key_fingerprint(&public_key)?
+        );
+        let other_key = EncItKeyType::Ed25519.generate()?;
+        assert_ne!(
+            public_key_fingerprint(&key)?,
+            public_key_fingerprint(&other_key)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn match_fingerprint() {
+        let fingerprint = (0u8..32).collect::<Vec<u8>>();
+        for expected in [
+            grouped_fingerprint(&fingerprint),
+            grouped_fingerprint(&fingerprint).to_lowercase(),
+            numeric_fingerprint(&fingerprint),
+            key_id(&fingerprint),
+            hex::encode(&fingerprint),
+        ] {
+            assert!(matches_fingerprint(&fingerprint, &expected), "{}", expected);
+        }
+        let other = (1u8..33).collect::<Vec<u8>>();
+        for expected in [
+            grouped_fingerprint(&other),
+            numeric_fingerprint(&other),
+            key_id(&other),
+            // too short to identify the key
+            "0001 0203".to_string(),
+            String::new(),
+        ] {
+            assert!(
+                !matches_fingerprint(&fingerprint, &expected),
+                "{}",
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn symmetric_safety_number() -> Result<(), EncItError> {
+        let alice = public_key_fingerprint(&EncItKeyType::Ed25519.generate()?)?;
+        let bob = public_key_fingerprint(&EncItKeyType::Rsa.generate()?)?;
+        let safety = safety_number(&alice, &bob);
+        assert_eq!(safety, safety_number(&bob, &alice));
+        assert_eq!(safety.lines().count(), 3);
+        assert!(safety
+            .split_whitespace()
+            .all(|group| group.len() == 5 && group.chars().all(|c| c.is_ascii_digit())));
+        Ok(())
+    }
+}
@@ -7,10 +7,16 @@ use std::fs::create_dir;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
+mod armor;
 mod cmd;
+mod compression;
 mod config;
 mod enc;
 mod errors;
+mod fingerprint;
+mod qr;
+mod seen;
+mod stream;
 
 fn main() -> Result<(), EncItError> {
     env_logger::init();
@@ -0,0 +1,141 @@
+use crate::errors::EncItError;
+use qrcode::render::unicode::Dense1x2;
+use qrcode::{EcLevel, QrCode};
+
+/// byte mode capacity of a version 12 code (65x65 modules) with medium error correction,
+/// bigger codes do not fit an 80 columns terminal
+const MAX_CAPACITY: usize = 287;
+/// QR codes are meant for short messages, more frames are not scanned in practice
+const MAX_FRAMES: usize = 20;
+/// header of every frame when the data is split across several QR codes
+const FRAME_PREFIX: &str = "ENCIT";
+/// black modules on a white background whatever the terminal colors are,
+/// the scanners reject inverted codes
+const DARK_ON_LIGHT: &str = "\x1b[30;107m";
+const RESET_COLORS: &str = "\x1b[0m";
+
+/// The data as one or more QR codes with medium error correction, the frames of a split data
+/// start with "ENCIT <frame>/<frames> " and have to be concatenated without it.
+fn qr_frames(data: &[u8]) -> Result<Vec<QrCode>, EncItError> {
+    if data.len() <= MAX_CAPACITY {
+        return Ok(vec![encode(data)?]);
+    }
+    // the prefix length depends on the number of frames
+    let mut frames_len = 2;
+    loop {
+        let prefix_len = format!("{} {}/{} ", FRAME_PREFIX, frames_len, frames_len).len();
+        let chunk_len = MAX_CAPACITY - prefix_len;
+        let needed = data.len().div_ceil(chunk_len);
+        if needed > MAX_FRAMES {
+            return Err(EncItError::InvalidCommand(format!(
+                "the data needs {} QR codes, at most {} are shown",
+                needed, MAX_FRAMES
+            )));
+        }
+        if needed <= frames_len {
+            return data
+                .chunks(chunk_len)
+                .enumerate()
+                .map(|(i, chunk)| {
+                    let mut frame = format!("{} {}/{} ", FRAME_PREFIX, i + 1, needed).into_bytes();
+                    frame.extend_from_slice(chunk);
+                    encode(&frame)
+                })
+                .collect();
+        }
+        frames_len = needed;
+    }
+}
+
+fn encode(data: &[u8]) -> Result<QrCode, EncItError> {
+    QrCode::with_error_correction_level(data, EcLevel::M)
+        .map_err(|e| EncItError::EncodeError(format!("QR code: {}", e)))
+}
+
+/// unicode half blocks with the quiet zone, every terminal line shows two rows of modules
+fn render(qr_code: &QrCode) -> String {
+    qr_code
+        .render::<Dense1x2>()
+        .quiet_zone(true)
+        .build()
+        .lines()
+        .map(|line| format!("{}{}{}\n", DARK_ON_LIGHT, line, RESET_COLORS))
+        .collect()
+}
+
+/// the QR codes of the data, every code is preceded by the title and by its frame number
+pub fn render_qr_frames(data: &[u8], title: &str) -> Result<String, EncItError> {
+    let frames = qr_frames(data)?;
+    let mut rendered = String::new();
+    for (i, frame) in frames.iter().enumerate() {
+        if frames.len() > 1 {
+            rendered.push_str(&format!("{} ({}/{})\n", title, i + 1, frames.len()));
+        } else {
+            rendered.push_str(&format!("{}\n", title));
+        }
+        rendered.push_str(&render(frame));
+    }
+    Ok(rendered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use qrcode::Version;
+
+    #[test]
+    fn max_capacity() -> Result<(), EncItError> {
+        let qr_code = encode(&[0xa5; MAX_CAPACITY])?;
+        assert_eq!(qr_code.version(), Version::Normal(12));
+        assert_eq!(qr_code.error_correction_level(), EcLevel::M);
+        assert_eq!(encode(b"hello")?.version(), Version::Normal(1));
+        Ok(())
+    }
+
+    #[test]
+    fn split_frames() -> Result<(), EncItError> {
+        assert_eq!(qr_frames(b"short")?.len(), 1);
+        let frames = qr_frames(&[b'x'; 1000])?;
+        assert_eq!(frames.len(), 4);
+        assert!(frames
+            .iter()
+            .all(|frame| frame.width() <= encode(&[0; MAX_CAPACITY]).unwrap().width()));
+
+        let rendered = render_qr_frames(&[b'x'; 1000], "message")?;
+        assert!(rendered.starts_with("message (1/4)\n"));
+        assert!(rendered.contains("\nmessage (4/4)\n"));
+
+        assert_eq!(qr_frames(&[b'x'; 5500])?.len(), MAX_FRAMES);
+        assert!(matches!(
+            render_qr_frames(&[b'x'; 5501], "message"),
+            Err(EncItError::InvalidCommand(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn dark_on_light_with_quiet_zone() -> Result<(), EncItError> {
+        let qr_code = encode(b"hello")?;
+        let rendered = render(&qr_code);
+        // 4 light modules on each side
+        let width = qr_code.width() + 8;
+        assert_eq!(rendered.lines().count(), width.div_ceil(2));
+        for line in rendered.lines() {
+            let modules = line
+                .strip_prefix(DARK_ON_LIGHT)
+                .and_then(|line| line.strip_suffix(RESET_COLORS))
+                .unwrap();
+            assert_eq!(modules.chars().count(), width);
+        }
+        // the first line is the quiet zone, the dark modules are drawn with the foreground color
+        let first_line = rendered.lines().next().unwrap();
+        assert!(first_line[DARK_ON_LIGHT.len()..]
+            .trim_end_matches(RESET_COLORS)
+            .chars()
+            .all(|c| c == ' '));
+        // top left corner of the finder pattern after the quiet zone
+        let finder_line = rendered.lines().nth(2).unwrap();
+        assert_eq!(finder_line[DARK_ON_LIGHT.len()..].chars().nth(4), Some('█'));
+        Ok(())
+    }
+}
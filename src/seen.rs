@@ -0,0 +1,78 @@
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use crate::errors::EncItError;
+
+#[cfg(test)]
+use mockall::automock;
+
+/// file name of the seen message store, placed next to the configuration file
+pub const SEEN_MESSAGES_FILE: &str = "seen-messages";
+
+/// ids (`jti`) of the messages already decrypted, used to detect replays
+#[cfg_attr(test, automock)]
+pub trait EncItSeenMessages {
+    fn seen(&self, id: &str) -> Result<bool, EncItError>;
+    fn add(&self, id: &str) -> Result<(), EncItError>;
+}
+
+pub struct EncItSeenMessagesImpl {
+    path: PathBuf,
+}
+
+impl EncItSeenMessagesImpl {
+    pub fn new(config_path: &Path) -> Self {
+        EncItSeenMessagesImpl {
+            path: config_path.with_file_name(SEEN_MESSAGES_FILE),
+        }
+    }
+
+    fn ids(&self) -> Result<HashSet<String>, EncItError> {
+        if !self.path.exists() {
+            return Ok(HashSet::new());
+        }
+        BufReader::new(File::open(&self.path)?)
+            .lines()
+            .map(|line| line.map_err(|e| e.into()))
+            .collect()
+    }
+}
+
+impl EncItSeenMessages for EncItSeenMessagesImpl {
+    fn seen(&self, id: &str) -> Result<bool, EncItError> {
+        Ok(self.ids()?.contains(id))
+    }
+
+    fn add(&self, id: &str) -> Result<(), EncItError> {
+        if id.contains('\n') {
+            return Err(EncItError::DecodeError("invalid message id".to_string()));
+        }
+        let mut seen_file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(seen_file, "{}", id)?;
+        seen_file.flush().map_err(|e| e.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_seen() -> Result<(), EncItError> {
+        let cfg_dir = tempfile::tempdir()?;
+        let seen_messages = EncItSeenMessagesImpl::new(&cfg_dir.path().join("config.yml"));
+        assert!(!seen_messages.seen("id-1")?);
+        seen_messages.add("id-1")?;
+        seen_messages.add("id-2")?;
+        assert!(seen_messages.seen("id-1")?);
+        assert!(seen_messages.seen("id-2")?);
+        assert!(!seen_messages.seen("id-3")?);
+        assert!(cfg_dir.path().join(SEEN_MESSAGES_FILE).exists());
+        Ok(())
+    }
+}
@@ -0,0 +1,352 @@
+use std::io::{BufRead, Read, Write};
+
+use log::debug;
+use openssl::rand::rand_bytes;
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+use serde::{Deserialize, Serialize};
+
+use crate::enc::{EncIt, EncItDecryptOptions, EncItEncryptOptions, EncItMessage};
+use crate::errors::EncItError;
+
+/// first line of a stream message, it is followed by the signed and encrypted header line
+/// and by the encrypted chunks
+pub const STREAM_MAGIC: &[u8] = b"ENCIT-STREAM-1\n";
+pub const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+
+const KEY_LEN: usize = 32;
+const NONCE_PREFIX_LEN: usize = 7;
+const TAG_LEN: usize = 16;
+const MAX_HEADER_LEN: u64 = 1024 * 1024;
+const MAX_CHUNK_SIZE: usize = 16 * 1024 * 1024;
+const CHUNK_FLAG: u8 = 0;
+const LAST_CHUNK_FLAG: u8 = 1;
+
+/// chunk encryption parameters, sent signed and encrypted for the recipients
+#[derive(Debug, Serialize, Deserialize)]
+struct EncItStreamHeader {
+    cipher: String,
+    key: String,
+    nonce: String,
+    #[serde(rename = "chunkSize")]
+    chunk_size: usize,
+}
+
+/// Encrypts the reader content chunk by chunk: every chunk is sealed with AES-256-GCM using
+/// a nonce made by the header nonce prefix, the chunk counter and the last chunk flag,
+/// so truncated or reordered chunks fail the authentication.
+pub fn encrypt_stream(
+    enc_it: &dyn EncIt,
+    identity: Option<&str>,
+    friends: &[&str],
+    options: &EncItEncryptOptions,
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+) -> Result<(), EncItError> {
+    encrypt_stream_chunks(
+        enc_it,
+        identity,
+        friends,
+        options,
+        reader,
+        writer,
+        STREAM_CHUNK_SIZE,
+    )
+}
+
+fn encrypt_stream_chunks(
+    enc_it: &dyn EncIt,
+    identity: Option<&str>,
+    friends: &[&str],
+    options: &EncItEncryptOptions,
+    reader: &mut dyn Read,
+    writer: &mut dyn Write,
+    chunk_size: usize,
+) -> Result<(), EncItError> {
+    let mut key = [0u8; KEY_LEN];
+    rand_bytes(&mut key)?;
+    let mut nonce_prefix = [0u8; NONCE_PREFIX_LEN];
+    rand_bytes(&mut nonce_prefix)?;
+    let header = EncItStreamHeader {
+        cipher: "A256GCM".to_string(),
+        key: base64::encode(key),
+        nonce: base64::encode(nonce_prefix),
+        chunk_size,
+    };
+    let jwe_header = enc_it.encrypt(identity, friends, options, &serde_json::to_vec(&header)?)?;
+    writer.write_all(STREAM_MAGIC)?;
+    writer.write_all(jwe_header.as_bytes())?;
+    writer.write_all(b"\n")?;
+
+    let mut counter: u32 = 0;
+    let mut chunk = vec![0u8; chunk_size];
+    let mut chunk_len = read_chunk(reader, &mut chunk)?;
+    let mut next_chunk = vec![0u8; chunk_size];
+    loop {
+        let next_chunk_len = if chunk_len == chunk_size {
+            read_chunk(reader, &mut next_chunk)?
+        } else {
+            0
+        };
+        let flag = if next_chunk_len == 0 {
+            LAST_CHUNK_FLAG
+        } else {
+            CHUNK_FLAG
+        };
+        let mut tag = [0u8; TAG_LEN];
+        let ciphertext = encrypt_aead(
+            Cipher::aes_256_gcm(),
+            &key,
+            Some(&chunk_nonce(&nonce_prefix, counter, flag)),
+            &[],
+            &chunk[..chunk_len],
+            &mut tag,
+        )?;
+        writer.write_all(&[flag])?;
+        writer.write_all(&((ciphertext.len() + TAG_LEN) as u32).to_be_bytes())?;
+        writer.write_all(&ciphertext)?;
+        writer.write_all(&tag)?;
+        if flag == LAST_CHUNK_FLAG {
+            break;
+        }
+        std::mem::swap(&mut chunk, &mut next_chunk);
+        chunk_len = next_chunk_len;
+        counter = next_counter(counter)?;
+    }
+    writer.flush().map_err(|e| e.into())
+}
+
+/// Reads the encrypted header line of a stream message (the magic line has to be
+/// already consumed).
+pub fn read_stream_header(reader: &mut dyn BufRead) -> Result<String, EncItError> {
+    let mut jwe_header = Vec::new();
+    reader
+        .take(MAX_HEADER_LEN)
+        .read_until(b'\n', &mut jwe_header)?;
+    if jwe_header.last() != Some(&b'\n') {
+        return Err(EncItError::StreamError("invalid stream header".to_string()));
+    }
+    String::from_utf8(jwe_header).map_err(|e| e.into())
+}
+
+/// Decrypts the chunks following the stream header,
+/// the returned message contains the header information.
+/// The message id is recorded only once the last chunk is authenticated.
+pub fn decrypt_stream(
+    enc_it: &dyn EncIt,
+    jwe_header: &str,
+    identity: Option<&str>,
+    options: &EncItDecryptOptions,
+    reader: &mut dyn BufRead,
+    writer: &mut dyn Write,
+) -> Result<EncItMessage, EncItError> {
+    let header_options = EncItDecryptOptions {
+        defer_seen: true,
+        ..options.clone()
+    };
+    let header_message = enc_it.decrypt(jwe_header, identity, &header_options)?;
+    let header: EncItStreamHeader = serde_json::from_slice(header_message.payload())?;
+    debug!(
+        "stream cipher:{} chunk size:{}",
+        header.cipher, header.chunk_size
+    );
+    let key = base64::decode(&header.key)?;
+    let nonce_prefix = base64::decode(&header.nonce)?;
+    if header.cipher != "A256GCM"
+        || key.len() != KEY_LEN
+        || nonce_prefix.len() != NONCE_PREFIX_LEN
+        || header.chunk_size > MAX_CHUNK_SIZE
+    {
+        return Err(EncItError::StreamError(
+            "unsupported stream cipher".to_string(),
+        ));
+    }
+
+    let mut counter: u32 = 0;
+    let mut chunk = Vec::with_capacity(header.chunk_size + TAG_LEN);
+    loop {
+        let mut chunk_header = [0u8; 5];
+        if let Err(e) = reader.read_exact(&mut chunk_header) {
+            return Err(EncItError::StreamError(format!(
+                "truncated stream message: {}",
+                e
+            )));
+        }
+        let flag = chunk_header[0];
+        let chunk_len = u32::from_be_bytes([
+            chunk_header[1],
+            chunk_header[2],
+            chunk_header[3],
+            chunk_header[4],
+        ]) as usize;
+        if (flag != CHUNK_FLAG && flag != LAST_CHUNK_FLAG)
+            || chunk_len < TAG_LEN
+            || chunk_len > header.chunk_size + TAG_LEN
+        {
+            return Err(EncItError::StreamError("invalid chunk".to_string()));
+        }
+        chunk.resize(chunk_len, 0);
+        if let Err(e) = reader.read_exact(&mut chunk) {
+            return Err(EncItError::StreamError(format!(
+                "truncated stream message: {}",
+                e
+            )));
+        }
+        let (ciphertext, tag) = chunk.split_at(chunk_len - TAG_LEN);
+        let plain_chunk = decrypt_aead(
+            Cipher::aes_256_gcm(),
+            &key,
+            Some(&chunk_nonce(&nonce_prefix, counter, flag)),
+            &[],
+            ciphertext,
+            tag,
+        )
+        .map_err(|_| EncItError::StreamError(format!("chunk {} is not valid", counter)))?;
+        writer.write_all(&plain_chunk)?;
+        if flag == LAST_CHUNK_FLAG {
+            break;
+        }
+        counter = next_counter(counter)?;
+    }
+    if !reader.fill_buf()?.is_empty() {
+        return Err(EncItError::StreamError(
+            "unexpected data after the last chunk".to_string(),
+        ));
+    }
+    writer.flush()?;
+    enc_it.mark_seen(&header_message)?;
+    Ok(header_message)
+}
+
+fn read_chunk(reader: &mut dyn Read, chunk: &mut [u8]) -> Result<usize, EncItError> {
+    let mut chunk_len = 0;
+    while chunk_len < chunk.len() {
+        let read = reader.read(&mut chunk[chunk_len..])?;
+        if read == 0 {
+            break;
+        }
+        chunk_len += read;
+    }
+    Ok(chunk_len)
+}
+
+fn chunk_nonce(nonce_prefix: &[u8], counter: u32, flag: u8) -> Vec<u8> {
+    let mut nonce = Vec::with_capacity(12);
+    nonce.extend_from_slice(nonce_prefix);
+    nonce.extend_from_slice(&counter.to_be_bytes());
+    nonce.push(flag);
+    nonce
+}
+
+fn next_counter(counter: u32) -> Result<u32, EncItError> {
+    counter
+        .checked_add(1)
+        .ok_or_else(|| EncItError::StreamError("too many chunks".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufReader, Cursor};
+    use std::sync::{Arc, Mutex};
+
+    use crate::enc::MockEncIt;
+
+    use super::*;
+
+    #[test]
+    fn encrypt_decrypt_stream() -> Result<(), EncItError> {
+        for message_len in [0, 10, 16, 33] {
+            let message: Vec<u8> = (0..message_len).map(|i| i as u8).collect();
+            let encrypted = encrypt_test_stream(&message)?;
+            let decrypted = decrypt_test_stream(&encrypted)?;
+            assert_eq!(decrypted, message);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn truncated_stream() -> Result<(), EncItError> {
+        let message = [1u8; 40];
+        let encrypted = encrypt_test_stream(&message)?;
+        // drop the last chunk (flag + length + 8 bytes + tag)
+        let truncated = &encrypted[..encrypted.len() - (5 + 8 + TAG_LEN)];
+        assert!(matches!(
+            decrypt_test_stream(truncated),
+            Err(EncItError::StreamError(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn reordered_stream() -> Result<(), EncItError> {
+        let message = [2u8; 48];
+        let encrypted = encrypt_test_stream(&message)?;
+        let chunk_len = 5 + 16 + TAG_LEN;
+        let chunks_start = encrypted.len() - 3 * chunk_len;
+        let mut reordered = encrypted[..chunks_start].to_vec();
+        reordered
+            .extend_from_slice(&encrypted[chunks_start + chunk_len..chunks_start + 2 * chunk_len]);
+        reordered.extend_from_slice(&encrypted[chunks_start..chunks_start + chunk_len]);
+        reordered.extend_from_slice(&encrypted[chunks_start + 2 * chunk_len..]);
+        assert!(matches!(
+            decrypt_test_stream(&reordered),
+            Err(EncItError::StreamError(_))
+        ));
+        Ok(())
+    }
+
+    fn encrypt_test_stream(message: &[u8]) -> Result<Vec<u8>, EncItError> {
+        let mut encit_mock = MockEncIt::new();
+        encit_mock
+            .expect_encrypt()
+            .returning(|_, _, _, header| Ok(String::from_utf8(header.to_vec()).unwrap()));
+        let mut encrypted = Vec::new();
+        encrypt_stream_chunks(
+            &encit_mock,
+            Some("identity"),
+            &["friend"],
+            &EncItEncryptOptions::default(),
+            &mut Cursor::new(message),
+            &mut encrypted,
+            16,
+        )?;
+        Ok(encrypted)
+    }
+
+    fn decrypt_test_stream(encrypted: &[u8]) -> Result<Vec<u8>, EncItError> {
+        assert!(encrypted.starts_with(STREAM_MAGIC));
+        let mut encit_mock = MockEncIt::new();
+        encit_mock
+            .expect_decrypt()
+            .withf(|_, _, options| options.defer_seen)
+            .returning(|header, _, _| {
+                Ok(EncItMessage::new(
+                    Some("sender".to_string()),
+                    "receiver".to_string(),
+                    None,
+                    header.trim().as_bytes().to_vec(),
+                    true,
+                ))
+            });
+        let marked_seen = Arc::new(Mutex::new(false));
+        let marked_seen_clone = marked_seen.clone();
+        encit_mock.expect_mark_seen().returning(move |_| {
+            *marked_seen_clone.lock().unwrap() = true;
+            Ok(())
+        });
+        let mut decrypted = Vec::new();
+        let mut reader = BufReader::new(&encrypted[STREAM_MAGIC.len()..]);
+        let jwe_header = read_stream_header(&mut reader)?;
+        let result = decrypt_stream(
+            &encit_mock,
+            &jwe_header,
+            None,
+            &EncItDecryptOptions::default(),
+            &mut reader,
+            &mut decrypted,
+        );
+        // the message id is recorded only for a complete stream
+        assert_eq!(*marked_seen.lock().unwrap(), result.is_ok());
+        result?;
+        Ok(decrypted)
+    }
+}